@@ -3,20 +3,40 @@
 -- grant all privileges on petclinic.* to krabby@localhost;
 
 
+create table clinic (
+    id INTEGER UNSIGNED AUTO_INCREMENT PRIMARY KEY,
+    name varchar(100) not null
+) engine innodb;
+
+
 create table user (
     id INTEGER UNSIGNED AUTO_INCREMENT PRIMARY KEY,
     username varchar(50) unique,
-    password varchar(100) not null
+    password varchar(100) not null,
+    clinic_id integer unsigned not null,
+    is_admin boolean not null default false,
+    FOREIGN key (clinic_id) REFERENCES clinic(id)
 ) engine innodb;
 
 
--- username/password admin
-insert into user values (1,'admin', 'd033e22ae348aeb5660fc2140aec35850c4da997');
+-- additional clinics a user works at, besides their home clinic
+create table user_clinic (
+    user_id integer unsigned not null,
+    clinic_id integer unsigned not null,
+    PRIMARY KEY (user_id, clinic_id),
+    FOREIGN key (user_id) REFERENCES user(id) on delete cascade,
+    FOREIGN key (clinic_id) REFERENCES clinic(id) on delete cascade
+) engine innodb;
 
 
 create table vet(
     id INTEGER UNSIGNED AUTO_INCREMENT PRIMARY KEY,
-    name varchar(100)
+    name varchar(100),
+    active boolean not null default true,
+    clinic_id integer unsigned not null,
+    updated_at datetime null,
+    FOREIGN key (clinic_id) REFERENCES clinic(id),
+    FULLTEXT ft_vet (name)
 ) engine innodb;
 
 create table pet(
@@ -29,8 +49,13 @@ create table pet(
     vet_id integer unsigned null,
     created_at datetime,
     created_by integer unsigned not null,
-    FOREIGN key (vet_id) REFERENCES vet(id) on delete cascade,
-    FOREIGN key (created_by) REFERENCES user(id)   
+    clinic_id integer unsigned not null,
+    archived boolean not null default false,
+    updated_at datetime null,
+    FOREIGN key (vet_id) REFERENCES vet(id) on delete restrict,
+    FOREIGN key (created_by) REFERENCES user(id),
+    FOREIGN key (clinic_id) REFERENCES clinic(id),
+    FULLTEXT ft_pet (name, owner_name, owner_phone)
 ) engine innodb;
 
 create table visit(
@@ -40,16 +65,72 @@ create table visit(
       visit_date datetime not null,
       notes text,
       FOREIGN key (pet_id) REFERENCES pet(id) on delete cascade,
-      FOREIGN key (vet_id) REFERENCES vet(id) on delete cascade   
+      FOREIGN key (vet_id) REFERENCES vet(id) on delete restrict,
+      FULLTEXT ft_visit (notes)
+) engine innodb;
+
+create table ownership_transfer(
+      id INTEGER UNSIGNED AUTO_INCREMENT PRIMARY KEY,
+      pet_id integer unsigned not null,
+      previous_owner_name varchar(100),
+      previous_owner_phone varchar(20),
+      new_owner_name varchar(100),
+      new_owner_phone varchar(20),
+      reason varchar(255),
+      transferred_at datetime not null,
+      transferred_by integer unsigned not null,
+      FOREIGN key (pet_id) REFERENCES pet(id) on delete cascade,
+      FOREIGN key (transferred_by) REFERENCES user(id)
 ) engine innodb;
 
+create table saved_search(
+      id INTEGER UNSIGNED AUTO_INCREMENT PRIMARY KEY,
+      user_id integer unsigned not null,
+      name varchar(100) not null,
+      query varchar(1000) not null,
+      is_default boolean not null default false,
+      created_at datetime not null,
+      FOREIGN key (user_id) REFERENCES user(id) on delete cascade
+) engine innodb;
+
+create table api_token(
+      id INTEGER UNSIGNED AUTO_INCREMENT PRIMARY KEY,
+      user_id integer unsigned not null,
+      name varchar(100) not null,
+      prefix varchar(12) not null,
+      token_hash char(40) not null unique,
+      scope varchar(10) not null,
+      created_at datetime not null,
+      expires_at datetime not null,
+      last_used_at datetime,
+      FOREIGN key (user_id) REFERENCES user(id) on delete cascade
+) engine innodb;
 
-insert into vet values(1, "James Carter");
-insert into vet values(2, "Helen Leary");
-insert into vet values(3, "Linda Douglas");
-insert into vet values(4, "Rafael Ortega");
+create table webhook(
+      id INTEGER UNSIGNED AUTO_INCREMENT PRIMARY KEY,
+      clinic_id integer unsigned not null,
+      url varchar(500) not null,
+      events varchar(255) not null,
+      secret varchar(64) not null,
+      active boolean not null default true,
+      created_at datetime not null,
+      created_by integer unsigned not null,
+      FOREIGN key (clinic_id) REFERENCES clinic(id) on delete cascade,
+      FOREIGN key (created_by) REFERENCES user(id)
+) engine innodb;
 
-INSERT INTO pet VALUES(1, 'Felix', 'John Doe', '333', 3, 1, 1, '2022-01-01 9:00:00', 1);
-INSERT INTO pet VALUES(2, 'Chloe', 'Peter Falk', '333', 5, 2, 1, '2022-01-01 9:00:00', 1);
-INSERT INTO pet VALUES(3, 'Iru', 'Dr.Falken', '333', 8, 2, 3, '2022-01-01 9:00:00', 1);
-INSERT INTO pet VALUES(4, 'Willy', 'Harold Davis', '333', 10, 2, null, '2022-01-01 9:00:00', 1);
+create table webhook_delivery(
+      id INTEGER UNSIGNED AUTO_INCREMENT PRIMARY KEY,
+      webhook_id integer unsigned not null,
+      event varchar(50) not null,
+      payload text not null,
+      status varchar(10) not null,
+      attempts integer unsigned not null default 0,
+      next_attempt_at datetime not null,
+      response_status integer unsigned,
+      last_error varchar(500),
+      created_at datetime not null,
+      delivered_at datetime,
+      index (status, next_attempt_at),
+      FOREIGN key (webhook_id) REFERENCES webhook(id) on delete cascade
+) engine innodb;
@@ -0,0 +1,16 @@
+-- demo data, load after schema.sql
+
+insert into clinic values (1, 'Main branch');
+
+-- username/password admin
+insert into user values (1,'admin', 'd033e22ae348aeb5660fc2140aec35850c4da997', 1, true);
+
+insert into vet values(1, "James Carter", true, 1);
+insert into vet values(2, "Helen Leary", true, 1);
+insert into vet values(3, "Linda Douglas", true, 1);
+insert into vet values(4, "Rafael Ortega", true, 1);
+
+INSERT INTO pet VALUES(1, 'Felix', 'John Doe', '333', 3, 1, 1, '2022-01-01 9:00:00', 1, 1);
+INSERT INTO pet VALUES(2, 'Chloe', 'Peter Falk', '333', 5, 2, 1, '2022-01-01 9:00:00', 1, 1);
+INSERT INTO pet VALUES(3, 'Iru', 'Dr.Falken', '333', 8, 2, 3, '2022-01-01 9:00:00', 1, 1);
+INSERT INTO pet VALUES(4, 'Willy', 'Harold Davis', '333', 10, 2, null, '2022-01-01 9:00:00', 1, 1);
@@ -0,0 +1,44 @@
+-- MySQL dump 10.13  Distrib 8.0.32, for Linux (x86_64)
+--
+-- Host: localhost    Database: petclinic
+
+/*!40101 SET @OLD_CHARACTER_SET_CLIENT=@@CHARACTER_SET_CLIENT */;
+/*!40101 SET NAMES utf8mb4 */;
+
+DROP TABLE IF EXISTS `vets`;
+CREATE TABLE `vets` (
+  `id` int unsigned NOT NULL AUTO_INCREMENT,
+  `first_name` varchar(30) DEFAULT NULL,
+  `last_name` varchar(30) DEFAULT NULL,
+  PRIMARY KEY (`id`)
+) ENGINE=InnoDB;
+
+LOCK TABLES `vets` WRITE;
+INSERT INTO `vets` VALUES (1,'James','Carter'),(2,'Helen','Leary');
+UNLOCK TABLES;
+
+INSERT INTO specialties VALUES (1,'radiology'),(2,'surgery');
+INSERT INTO vet_specialties VALUES (2,1),(2,2);
+INSERT INTO types VALUES (1,'cat'),(2,'dog'),(3,'snake');
+
+# owners written by hand, with a column list and the schema name
+INSERT INTO `petclinic`.`owners` (`id`, `first_name`, `last_name`, `address`, `city`, `telephone`)
+VALUES (1, 'George', 'Franklin', '110 W. Liberty St.', 'Madison', '6085551023'),
+       (2, 'Betty', 'Davis', '', '', '6085551749'),
+       (3, 'Eduardo', 'Rodriquez', '2693 Commerce St.', 'McFarland', '6085558763');
+
+INSERT IGNORE INTO pets VALUES
+  (1,'Leo','2010-09-07',1,1),
+  (2,'Basil','2012-08-06 00:00:00',2,2),
+  (3,'Rosy','unknown',2,2),
+  (4,'Jewel','2010-03-07',3,2),
+  (5,'Iggy','2010-11-30',1,9);
+
+INSERT INTO visits VALUES
+  (1,1,'2013-01-01','rabies shot; it''s done'),
+  (2,4,'2013-01-02','molting'),
+  (3,2,'2013-01-03',NULL),
+  (4,1,'soon','checkup');
+
+/* not a Spring table */
+INSERT INTO audit_log VALUES (1,'imported');
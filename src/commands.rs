@@ -0,0 +1,136 @@
+use std::{error::Error, fs, io};
+
+use argh::FromArgs;
+use petclinic::Env;
+use tracing::info;
+
+use crate::{
+    connect_database,
+    logic::{backup, clinics::Scope, spring, sql_dump, vets},
+};
+
+#[derive(FromArgs)]
+#[argh(subcommand)]
+pub enum Command {
+    Backup(BackupCommand),
+    Restore(RestoreCommand),
+    ImportSpring(ImportSpringCommand),
+}
+
+#[derive(FromArgs)]
+/// write the whole database to a JSON archive
+#[argh(subcommand, name = "backup")]
+pub struct BackupCommand {
+    /// archive file to write, standard output by default
+    #[argh(option, short = 'o')]
+    output: Option<String>,
+
+    /// leave the password hashes out of the archive
+    #[argh(switch)]
+    without_passwords: bool,
+}
+
+#[derive(FromArgs)]
+/// load a JSON archive into an empty database
+#[argh(subcommand, name = "restore")]
+pub struct RestoreCommand {
+    /// archive file made by `petclinic backup`
+    #[argh(positional)]
+    input: String,
+
+    /// password for the accounts archived without one, they stay locked otherwise
+    #[argh(option)]
+    password: Option<String>,
+}
+
+#[derive(FromArgs)]
+/// import owners, pets, vets and visits from a SQL dump of Spring Petclinic
+#[argh(subcommand, name = "import-spring")]
+pub struct ImportSpringCommand {
+    /// SQL dump of the Spring database
+    #[argh(positional)]
+    input: String,
+
+    /// clinic receiving the records
+    #[argh(option, default = "1")]
+    clinic: u32,
+
+    /// user recorded as creator of the pets
+    #[argh(option, default = "1")]
+    user: u32,
+
+    /// name of the vet given to the visits, Spring visits have none
+    #[argh(option)]
+    visit_vet: Option<String>,
+
+    /// only report what would be imported
+    #[argh(switch)]
+    dry_run: bool,
+}
+
+pub async fn run(env: &Env, command: Command) -> Result<(), Box<dyn Error>> {
+    let rb = connect_database(env).await;
+
+    match command {
+        Command::Backup(backup_command) => {
+            let archive = backup::create(&rb, !backup_command.without_passwords).await?;
+            match backup_command.output {
+                Some(path) => serde_json::to_writer_pretty(fs::File::create(&path)?, &archive)?,
+                None => serde_json::to_writer_pretty(io::stdout(), &archive)?,
+            }
+            info!(
+                "Backup done: {} pets, {} vets, {} visits",
+                archive.pets.len(),
+                archive.vets.len(),
+                archive.visits.len()
+            );
+        }
+        Command::Restore(restore_command) => {
+            let file = fs::File::open(&restore_command.input)?;
+            let archive: backup::Archive = serde_json::from_reader(io::BufReader::new(file))?;
+            backup::restore(&rb, &archive, restore_command.password.as_deref()).await?;
+            info!(
+                "Restore done: {} pets, {} vets, {} visits",
+                archive.pets.len(),
+                archive.vets.len(),
+                archive.visits.len()
+            );
+        }
+        Command::ImportSpring(import_command) => {
+            let sql = fs::read_to_string(&import_command.input)?;
+            let dump = sql_dump::parse(&sql, spring::columns_of)?;
+            let existing_vets = vets::active(&rb, &Scope::Clinic(import_command.clinic))
+                .await?
+                .into_iter()
+                .map(|v| (v.name.trim().to_lowercase(), v.id))
+                .collect();
+            let plan = spring::plan(
+                &dump,
+                import_command.clinic,
+                import_command.user,
+                import_command.visit_vet.as_deref(),
+                &existing_vets,
+            )?;
+
+            if !import_command.dry_run {
+                spring::import(&rb, &plan).await?;
+            }
+            for message in &plan.unmapped {
+                println!("not mapped: {}", message);
+            }
+            println!(
+                "{} vets, {} pets and {} visits {}",
+                plan.vets.len(),
+                plan.pets.len(),
+                plan.visits.len(),
+                if import_command.dry_run {
+                    "would be imported"
+                } else {
+                    "imported"
+                }
+            );
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,258 @@
+//! JSON API under `/api/v1`, on top of the same `logic` functions as the HTML pages.
+//!
+//! Errors are answered as `{"error": {"status": .., "message": .., "fields": [..]}}`.
+
+pub mod openapi;
+pub mod pets;
+pub mod vets;
+pub mod visits;
+
+use axum::{
+    async_trait,
+    extract::{rejection::JsonRejection, Extension, FromRequest, Json, RequestParts},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use rbatis::plugin::page::Page;
+use serde::{Deserialize, Deserializer, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+    logic::{clinics::ActiveClinic, pets::Pet, tokens, users::User, vets::Vet, visits::Visit},
+    Context,
+};
+
+use std::sync::Arc;
+
+/// Problem with one field of a request body
+#[derive(Serialize, Clone, Debug, ToSchema)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub message: String,
+    pub fields: Vec<FieldError>,
+    /// anything else the client can use, e.g. the records in conflict
+    pub details: Option<Box<serde_json::Value>>,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, message: &str) -> ApiError {
+        ApiError {
+            status,
+            message: message.to_string(),
+            fields: Vec::new(),
+            details: None,
+        }
+    }
+
+    pub fn not_found(what: &str) -> ApiError {
+        ApiError::new(StatusCode::NOT_FOUND, &format!("{} not found", what))
+    }
+
+    pub fn conflict(message: &str, details: serde_json::Value) -> ApiError {
+        ApiError {
+            details: Some(Box::new(details)),
+            ..ApiError::new(StatusCode::CONFLICT, message)
+        }
+    }
+
+    pub fn invalid(fields: Vec<FieldError>) -> ApiError {
+        ApiError {
+            fields,
+            ..ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, "invalid request body")
+        }
+    }
+}
+
+/// Body of every error answer
+#[derive(Serialize, ToSchema)]
+pub struct ErrorBody {
+    pub error: ErrorInfo,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ErrorInfo {
+    pub status: u16,
+    pub message: String,
+    pub fields: Vec<FieldError>,
+    #[schema(value_type = Option<Object>)]
+    pub details: Option<Box<serde_json::Value>>,
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = ErrorBody {
+            error: ErrorInfo {
+                status: self.status.as_u16(),
+                message: self.message,
+                fields: self.fields,
+                details: self.details,
+            },
+        };
+
+        (self.status, Json(body)).into_response()
+    }
+}
+
+impl From<rbatis::Error> for ApiError {
+    fn from(e: rbatis::Error) -> Self {
+        tracing::error!("API database error: {}", e);
+        ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "internal error")
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        let status = match rejection {
+            JsonRejection::JsonDataError(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            JsonRejection::MissingJsonContentType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            _ => StatusCode::BAD_REQUEST,
+        };
+        ApiError::new(status, &rejection.to_string())
+    }
+}
+
+/// Collects the field errors of a request body
+#[derive(Default)]
+pub struct Validation {
+    fields: Vec<FieldError>,
+}
+
+impl Validation {
+    pub fn error(&mut self, field: &str, message: &str) {
+        self.fields.push(FieldError {
+            field: field.to_string(),
+            message: message.to_string(),
+        });
+    }
+
+    pub fn check(&mut self, valid: bool, field: &str, message: &str) {
+        if !valid {
+            self.error(field, message);
+        }
+    }
+
+    pub fn result(self) -> Result<(), ApiError> {
+        if self.fields.is_empty() {
+            Ok(())
+        } else {
+            Err(ApiError::invalid(self.fields))
+        }
+    }
+}
+
+/// Request body, answering 422 and friends as JSON
+pub struct Body<T>(pub T);
+
+#[async_trait]
+impl<T, B> FromRequest<B> for Body<T>
+where
+    T: serde::de::DeserializeOwned,
+    B: axum::body::HttpBody + Send,
+    B::Data: Send,
+    B::Error: Into<axum::BoxError>,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<T>::from_request(req).await?;
+        Ok(Body(value))
+    }
+}
+
+/// Caller of an API call: the owner of an `Authorization: Bearer` token, or else the user of
+/// the session cookie. Answers 401 instead of redirecting to the login page.
+#[derive(Clone)]
+pub struct ApiUser(pub User);
+
+#[async_trait]
+impl<B> FromRequest<B> for ApiUser
+where
+    B: Send,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        // already checked by the route layer
+        if let Some(user) = req.extensions().get::<ApiUser>() {
+            return Ok(user.clone());
+        }
+
+        let bearer = req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(|token| token.trim().to_string());
+        let secret = match bearer {
+            Some(secret) => secret,
+            None => {
+                let user = User::from_request(req).await.map(ApiUser).map_err(|_| {
+                    ApiError::new(StatusCode::UNAUTHORIZED, "authentication required")
+                })?;
+                req.extensions_mut().insert(user.clone());
+                return Ok(user);
+            }
+        };
+
+        let Extension(state) = Extension::<Arc<Context>>::from_request(req)
+            .await
+            .map_err(|_| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "internal error"))?;
+        let (user, token) = tokens::authenticate(&state.rb, &secret)
+            .await?
+            .ok_or_else(|| ApiError::new(StatusCode::UNAUTHORIZED, "invalid or expired token"))?;
+        if !token.scope().allows(req.method(), req.uri().path()) {
+            return Err(ApiError::new(
+                StatusCode::FORBIDDEN,
+                "the token only allows read requests",
+            ));
+        }
+
+        // token calls work on the home clinic of their user
+        req.extensions_mut().insert(ActiveClinic {
+            id: user.clinic_id,
+            is_admin: user.is_admin,
+        });
+        let user = ApiUser(user);
+        req.extensions_mut().insert(user.clone());
+
+        Ok(user)
+    }
+}
+
+/// One page of a listing
+#[derive(Serialize, ToSchema)]
+#[aliases(PetPage = PageBody<Pet>, VetPage = PageBody<Vet>, VisitPage = PageBody<Visit>)]
+pub struct PageBody<T> {
+    pub items: Vec<T>,
+    pub page: u64,
+    pub page_size: u64,
+    pub total: u64,
+    pub pages: u64,
+}
+
+impl<T> From<Page<T>> for PageBody<T> {
+    fn from(page: Page<T>) -> Self {
+        PageBody {
+            items: page.records,
+            page: page.page_no,
+            page_size: page.page_size,
+            total: page.total,
+            pages: page.pages,
+        }
+    }
+}
+
+/// For PATCH bodies: tells a missing field (`None`) from an explicit null (`Some(None)`)
+pub fn nullable<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
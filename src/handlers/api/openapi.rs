@@ -0,0 +1,105 @@
+use crate::{
+    handlers::api::{
+        pets::{self, PetInput, PetPatch},
+        vets::{self, VetInput, VetPatch},
+        visits::{self, VisitInput, VisitPatch},
+        ErrorBody, ErrorInfo, FieldError, PetPage, VetPage, VisitPage,
+    },
+    logic::{pets::Pet, vets::Vet, visits::Visit},
+};
+use axum::{
+    extract::{Extension, Json},
+    response::Html,
+};
+
+use tera::Tera;
+use utoipa::{
+    openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme},
+    Modify, OpenApi,
+};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Axum Petclinic API", version = "1"),
+    paths(
+        pets::list,
+        pets::get,
+        pets::create,
+        pets::update,
+        pets::patch,
+        pets::delete,
+        vets::list,
+        vets::get,
+        vets::create,
+        vets::update,
+        vets::patch,
+        vets::delete,
+        visits::list,
+        visits::get,
+        visits::create,
+        visits::update,
+        visits::patch,
+        visits::delete,
+    ),
+    components(schemas(
+        Pet,
+        PetInput,
+        PetPatch,
+        PetPage,
+        Vet,
+        VetInput,
+        VetPatch,
+        VetPage,
+        Visit,
+        VisitInput,
+        VisitPatch,
+        VisitPage,
+        ErrorBody,
+        ErrorInfo,
+        FieldError,
+    )),
+    modifiers(&Authentication),
+    security(("session" = []), ("token" = [])),
+    tags(
+        (name = "pets"),
+        (name = "vets"),
+        (name = "visits"),
+    )
+)]
+pub struct ApiDoc;
+
+/// Calls are authenticated by the session cookie set at login, or by a personal access token
+struct Authentication;
+
+impl Modify for Authentication {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "session",
+            SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::new("axum_session"))),
+        );
+        components.add_security_scheme(
+            "token",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .description(Some(
+                        "Personal access token, created on the API tokens page",
+                    ))
+                    .build(),
+            ),
+        );
+    }
+}
+
+pub async fn spec() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// Lists the operations of the spec and lets logged in users try them
+pub async fn explorer(Extension(tera): Extension<Tera>) -> Html<String> {
+    let c = tera::Context::new();
+    let r = tera.render("api/explorer.html", &c).unwrap();
+
+    Html::from(r)
+}
@@ -0,0 +1,366 @@
+use crate::{
+    handlers::{
+        api::{nullable, ApiError, ApiUser, Body, PageBody, Validation},
+        conditional::Version,
+        negotiate::Accept,
+    },
+    logic::{
+        clinics::{ActiveClinic, Scope},
+        events::{self, Action, Change},
+        ownership,
+        paging::Paging,
+        pets::{self, Pet, PetFilter},
+        vets, webhooks,
+    },
+    Context,
+};
+use axum::{
+    extract::{Extension, Json, Path, Query},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+
+use chrono::Utc;
+use serde::Deserialize;
+use serde_json::json;
+use utoipa::ToSchema;
+
+use std::{collections::HashMap, sync::Arc};
+
+#[derive(Deserialize, ToSchema)]
+pub struct PetInput {
+    name: String,
+    #[serde(default)]
+    owner_name: String,
+    #[serde(default)]
+    owner_phone: String,
+    #[serde(default)]
+    age: u32,
+    pet_type: u32,
+    #[serde(default)]
+    vet_id: Option<u32>,
+    /// create the pet even when it looks like a duplicate
+    #[serde(default)]
+    confirmed: bool,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct PetPatch {
+    name: Option<String>,
+    owner_name: Option<String>,
+    owner_phone: Option<String>,
+    age: Option<u32>,
+    pet_type: Option<u32>,
+    /// null removes the vet
+    #[serde(default, deserialize_with = "nullable")]
+    #[schema(value_type = Option<u32>, nullable)]
+    vet_id: Option<Option<u32>>,
+}
+
+async fn validate(state: &Context, pet: &Pet) -> Result<(), ApiError> {
+    let mut v = Validation::default();
+    v.check(!pet.name.trim().is_empty(), "name", "is required");
+    v.check(
+        !pet.owner_name.trim().is_empty(),
+        "owner_name",
+        "is required",
+    );
+    v.check(
+        pet.owner_phone.is_empty() || pets::is_valid_phone(&pet.owner_phone),
+        "owner_phone",
+        "is not a valid phone number",
+    );
+    v.check(pet.age <= pets::MAX_AGE, "age", "is too large");
+    v.check(
+        pets::types().contains_key(&pet.pet_type),
+        "pet_type",
+        "is not a known pet type",
+    );
+    if let Some(vet_id) = pet.vet_id {
+        let vet = vets::get(&state.rb, &Scope::Clinic(pet.clinic_id), vet_id).await?;
+        v.check(
+            vet.is_some_and(|v| v.active),
+            "vet_id",
+            "is not an active vet of the pet's clinic",
+        );
+    }
+
+    v.result()
+}
+
+/// Saves `updated`, recording a change of owner as an ownership transfer
+async fn update_pet(
+    state: &Context,
+    user_id: u32,
+    current: &Pet,
+    updated: Pet,
+) -> Result<Response, ApiError> {
+    validate(state, &updated).await?;
+
+    if updated.owner_name != current.owner_name || updated.owner_phone != current.owner_phone {
+        let updated = Pet {
+            owner_name: updated.owner_name.trim().to_string(),
+            owner_phone: updated.owner_phone.trim().to_string(),
+            ..updated.clone()
+        };
+        let reason = "Updated through the API";
+        ownership::save_with_transfer(&state.rb, current, &updated, reason, user_id).await?;
+    } else {
+        pets::save(&state.rb, &updated).await?;
+    }
+    // read back, with the time of the change
+    let saved = find(state, &Scope::Clinic(updated.clinic_id), updated.id).await?;
+    webhooks::publish(&state.rb, saved.clinic_id, webhooks::PET_UPDATED, &saved).await;
+    events::publish(state, Change::pet(Action::Updated, &saved));
+
+    let version = version_of(&saved);
+    Ok(version.apply(Json(saved).into_response()))
+}
+
+fn version_of(pet: &Pet) -> Version {
+    Version::of(pet, Accept::Json, Some(pet.last_modified()))
+}
+
+async fn find(state: &Context, scope: &Scope, id: u32) -> Result<Pet, ApiError> {
+    pets::get(&state.rb, scope, id)
+        .await?
+        .ok_or_else(|| ApiError::not_found("pet"))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/pets",
+    tag = "pets",
+    params(
+        ("q" = Option<String>, Query, description = "Search expression, e.g. `type:dog vet:carter age>5`"),
+        ("name" = Option<String>, Query, description = "Part of the name"),
+        ("pet_type" = Option<u32>, Query, description = "Pet type id"),
+        ("vet_id" = Option<u32>, Query, description = "0 for pets without a vet"),
+        ("min_age" = Option<u32>, Query, description = "Minimum age"),
+        ("max_age" = Option<u32>, Query, description = "Maximum age"),
+        ("owner_name" = Option<String>, Query, description = "Part of the owner name"),
+        ("owner_phone" = Option<String>, Query, description = "Part of the owner phone"),
+        ("created_from" = Option<String>, Query, description = "YYYY-MM-DD"),
+        ("created_to" = Option<String>, Query, description = "YYYY-MM-DD"),
+        Paging),
+    responses(
+        (status = 200, description = "One page of pets", body = PetPage),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+        (status = 422, description = "Invalid filters", body = ErrorBody),
+    )
+)]
+pub async fn list(
+    Extension(state): Extension<Arc<Context>>,
+    _user: ApiUser,
+    scope: Scope,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<PageBody<Pet>>, ApiError> {
+    let filter = PetFilter::from_query(&params);
+    if let Some(e) = &filter.query_error {
+        let mut v = Validation::default();
+        v.error("q", &e.to_string());
+        v.result()?;
+    }
+    let paging = Paging::from_query(&params, pets::SORTABLE);
+    let page = pets::search(&state.rb, &scope, &filter, &paging).await?;
+
+    Ok(Json(page.into()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/pets/{id}",
+    tag = "pets",
+    params(
+        ("id" = u32, Path, description = "Pet id"),
+        ("If-None-Match" = Option<String>, Header, description = "ETag of a copy already read"),
+    ),
+    responses(
+        (status = 200, description = "The pet, with its ETag and Last-Modified", body = Pet),
+        (status = 304, description = "The copy with the If-None-Match ETag is current"),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+        (status = 404, description = "Unknown pet", body = ErrorBody),
+    )
+)]
+pub async fn get(
+    Extension(state): Extension<Arc<Context>>,
+    _user: ApiUser,
+    scope: Scope,
+    headers: HeaderMap,
+    Path(id): Path<u32>,
+) -> Result<Response, ApiError> {
+    let pet = find(&state, &scope, id).await?;
+    let version = version_of(&pet);
+
+    Ok(version.respond(&headers, Json(pet).into_response()))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/pets",
+    tag = "pets",
+    request_body = PetInput,
+    responses(
+        (status = 201, description = "Created, its url is in the Location header", body = Pet),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+        (status = 409, description = "Possible duplicate, `details.duplicates` lists them", body = ErrorBody),
+        (status = 422, description = "Invalid body", body = ErrorBody),
+    )
+)]
+pub async fn create(
+    Extension(state): Extension<Arc<Context>>,
+    Extension(clinic): Extension<ActiveClinic>,
+    ApiUser(user): ApiUser,
+    Body(input): Body<PetInput>,
+) -> Result<Response, ApiError> {
+    let mut pet = Pet {
+        id: 0,
+        name: input.name,
+        owner_name: input.owner_name,
+        owner_phone: input.owner_phone,
+        age: input.age,
+        pet_type: input.pet_type,
+        vet_id: input.vet_id,
+        created_at: Utc::now().naive_utc(),
+        created_by: user.id,
+        clinic_id: clinic.id,
+        archived: false,
+        updated_at: None,
+    };
+    validate(&state, &pet).await?;
+
+    if !input.confirmed {
+        let duplicates = pets::find_duplicates(&state.rb, &pet).await?;
+        if !duplicates.is_empty() {
+            return Err(ApiError::conflict(
+                "possible duplicate, send `confirmed: true` to create it anyway",
+                json!({ "duplicates": duplicates }),
+            ));
+        }
+    }
+    pet.id = pets::save(&state.rb, &pet).await?;
+    webhooks::publish(&state.rb, pet.clinic_id, webhooks::PET_CREATED, &pet).await;
+    events::publish(&state, Change::pet(Action::Created, &pet));
+
+    Ok((
+        StatusCode::CREATED,
+        [(header::LOCATION, format!("/api/v1/pets/{}", pet.id))],
+        Json(pet),
+    )
+        .into_response())
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/pets/{id}",
+    tag = "pets",
+    params(
+        ("id" = u32, Path, description = "Pet id"),
+        ("If-Match" = Option<String>, Header, description = "ETag of the version the change is based on"),
+    ),
+    request_body = PetInput,
+    responses(
+        (status = 200, description = "Replaced", body = Pet),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+        (status = 404, description = "Unknown pet", body = ErrorBody),
+        (status = 412, description = "Changed since the If-Match version", body = ErrorBody),
+        (status = 422, description = "Invalid body", body = ErrorBody),
+    )
+)]
+pub async fn update(
+    Extension(state): Extension<Arc<Context>>,
+    ApiUser(user): ApiUser,
+    scope: Scope,
+    headers: HeaderMap,
+    Path(id): Path<u32>,
+    Body(input): Body<PetInput>,
+) -> Result<Response, ApiError> {
+    let current = find(&state, &scope, id).await?;
+    version_of(&current).check_if_match(&headers, "pet")?;
+    let updated = Pet {
+        name: input.name,
+        owner_name: input.owner_name,
+        owner_phone: input.owner_phone,
+        age: input.age,
+        pet_type: input.pet_type,
+        vet_id: input.vet_id,
+        ..current.clone()
+    };
+
+    update_pet(&state, user.id, &current, updated).await
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/v1/pets/{id}",
+    tag = "pets",
+    params(
+        ("id" = u32, Path, description = "Pet id"),
+        ("If-Match" = Option<String>, Header, description = "ETag of the version the change is based on"),
+    ),
+    request_body = PetPatch,
+    responses(
+        (status = 200, description = "Updated", body = Pet),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+        (status = 404, description = "Unknown pet", body = ErrorBody),
+        (status = 412, description = "Changed since the If-Match version", body = ErrorBody),
+        (status = 422, description = "Invalid body", body = ErrorBody),
+    )
+)]
+pub async fn patch(
+    Extension(state): Extension<Arc<Context>>,
+    ApiUser(user): ApiUser,
+    scope: Scope,
+    headers: HeaderMap,
+    Path(id): Path<u32>,
+    Body(input): Body<PetPatch>,
+) -> Result<Response, ApiError> {
+    let current = find(&state, &scope, id).await?;
+    version_of(&current).check_if_match(&headers, "pet")?;
+    let updated = Pet {
+        name: input.name.unwrap_or_else(|| current.name.clone()),
+        owner_name: input
+            .owner_name
+            .unwrap_or_else(|| current.owner_name.clone()),
+        owner_phone: input
+            .owner_phone
+            .unwrap_or_else(|| current.owner_phone.clone()),
+        age: input.age.unwrap_or(current.age),
+        pet_type: input.pet_type.unwrap_or(current.pet_type),
+        vet_id: input.vet_id.unwrap_or(current.vet_id),
+        ..current.clone()
+    };
+
+    update_pet(&state, user.id, &current, updated).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/pets/{id}",
+    tag = "pets",
+    params(
+        ("id" = u32, Path, description = "Pet id"),
+        ("If-Match" = Option<String>, Header, description = "ETag of the version the change is based on"),
+    ),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+        (status = 404, description = "Unknown pet", body = ErrorBody),
+        (status = 412, description = "Changed since the If-Match version", body = ErrorBody),
+    )
+)]
+pub async fn delete(
+    Extension(state): Extension<Arc<Context>>,
+    _user: ApiUser,
+    scope: Scope,
+    headers: HeaderMap,
+    Path(id): Path<u32>,
+) -> Result<StatusCode, ApiError> {
+    let pet = find(&state, &scope, id).await?;
+    version_of(&pet).check_if_match(&headers, "pet")?;
+    pets::delete(&state.rb, &pet).await?;
+    webhooks::publish(&state.rb, pet.clinic_id, webhooks::PET_DELETED, &pet).await;
+    events::publish(&state, Change::pet(Action::Deleted, &pet));
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,258 @@
+use crate::{
+    handlers::{
+        api::{ApiError, ApiUser, Body, PageBody, Validation},
+        conditional::Version,
+        negotiate::Accept,
+    },
+    logic::{
+        clinics::{ActiveClinic, Scope},
+        events::{self, Action, Change},
+        paging::Paging,
+        vets::{self, Vet},
+    },
+    Context,
+};
+use axum::{
+    extract::{Extension, Json, Path, Query},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+
+use serde::Deserialize;
+use serde_json::json;
+use utoipa::ToSchema;
+
+use std::{collections::HashMap, sync::Arc};
+
+#[derive(Deserialize, ToSchema)]
+pub struct VetInput {
+    name: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct VetPatch {
+    name: Option<String>,
+}
+
+fn validate(vet: &Vet) -> Result<(), ApiError> {
+    let mut v = Validation::default();
+    v.check(!vet.name.trim().is_empty(), "name", "is required");
+
+    v.result()
+}
+
+fn version_of(vet: &Vet) -> Version {
+    Version::of(vet, Accept::Json, vet.updated_at)
+}
+
+/// Saves the changes of an existing vet and answers with its new version
+async fn update_vet(state: &Context, vet: Vet) -> Result<Response, ApiError> {
+    validate(&vet)?;
+    vets::save(&state.rb, &vet).await?;
+    // read back, with the time of the change
+    let saved = find(state, &Scope::Clinic(vet.clinic_id), vet.id).await?;
+    events::publish(state, Change::vet(Action::Updated, &saved));
+
+    let version = version_of(&saved);
+    Ok(version.apply(Json(saved).into_response()))
+}
+
+async fn find(state: &Context, scope: &Scope, id: u32) -> Result<Vet, ApiError> {
+    vets::get(&state.rb, scope, id)
+        .await?
+        .ok_or_else(|| ApiError::not_found("vet"))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/vets",
+    tag = "vets",
+    params(
+        ("name" = Option<String>, Query, description = "Part of the name"),
+        Paging),
+    responses(
+        (status = 200, description = "One page of vets", body = VetPage),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+        (status = 422, description = "Invalid filters", body = ErrorBody),
+    )
+)]
+pub async fn list(
+    Extension(state): Extension<Arc<Context>>,
+    _user: ApiUser,
+    scope: Scope,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<PageBody<Vet>>, ApiError> {
+    let paging = Paging::from_query(&params, vets::SORTABLE);
+    let page = vets::search(&state.rb, &scope, params.get("name"), &paging).await?;
+
+    Ok(Json(page.into()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/vets/{id}",
+    tag = "vets",
+    params(
+        ("id" = u32, Path, description = "Vet id"),
+        ("If-None-Match" = Option<String>, Header, description = "ETag of a copy already read"),
+    ),
+    responses(
+        (status = 200, description = "The vet, with its ETag and Last-Modified", body = Vet),
+        (status = 304, description = "The copy with the If-None-Match ETag is current"),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+        (status = 404, description = "Unknown vet", body = ErrorBody),
+    )
+)]
+pub async fn get(
+    Extension(state): Extension<Arc<Context>>,
+    _user: ApiUser,
+    scope: Scope,
+    headers: HeaderMap,
+    Path(id): Path<u32>,
+) -> Result<Response, ApiError> {
+    let vet = find(&state, &scope, id).await?;
+    let version = version_of(&vet);
+
+    Ok(version.respond(&headers, Json(vet).into_response()))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/vets",
+    tag = "vets",
+    request_body = VetInput,
+    responses(
+        (status = 201, description = "Created, its url is in the Location header", body = Vet),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+        (status = 422, description = "Invalid body", body = ErrorBody),
+    )
+)]
+pub async fn create(
+    Extension(state): Extension<Arc<Context>>,
+    Extension(clinic): Extension<ActiveClinic>,
+    _user: ApiUser,
+    Body(input): Body<VetInput>,
+) -> Result<Response, ApiError> {
+    let mut vet = Vet {
+        id: 0,
+        name: input.name,
+        active: true,
+        clinic_id: clinic.id,
+        updated_at: None,
+    };
+    validate(&vet)?;
+    vet.id = vets::save(&state.rb, &vet).await?;
+    events::publish(&state, Change::vet(Action::Created, &vet));
+
+    Ok((
+        StatusCode::CREATED,
+        [(header::LOCATION, format!("/api/v1/vets/{}", vet.id))],
+        Json(vet),
+    )
+        .into_response())
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/vets/{id}",
+    tag = "vets",
+    params(
+        ("id" = u32, Path, description = "Vet id"),
+        ("If-Match" = Option<String>, Header, description = "ETag of the version the change is based on"),
+    ),
+    request_body = VetInput,
+    responses(
+        (status = 200, description = "Replaced", body = Vet),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+        (status = 404, description = "Unknown vet", body = ErrorBody),
+        (status = 412, description = "Changed since the If-Match version", body = ErrorBody),
+        (status = 422, description = "Invalid body", body = ErrorBody),
+    )
+)]
+pub async fn update(
+    Extension(state): Extension<Arc<Context>>,
+    _user: ApiUser,
+    scope: Scope,
+    headers: HeaderMap,
+    Path(id): Path<u32>,
+    Body(input): Body<VetInput>,
+) -> Result<Response, ApiError> {
+    let mut vet = find(&state, &scope, id).await?;
+    version_of(&vet).check_if_match(&headers, "vet")?;
+    vet.name = input.name;
+
+    update_vet(&state, vet).await
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/v1/vets/{id}",
+    tag = "vets",
+    params(
+        ("id" = u32, Path, description = "Vet id"),
+        ("If-Match" = Option<String>, Header, description = "ETag of the version the change is based on"),
+    ),
+    request_body = VetPatch,
+    responses(
+        (status = 200, description = "Updated", body = Vet),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+        (status = 404, description = "Unknown vet", body = ErrorBody),
+        (status = 412, description = "Changed since the If-Match version", body = ErrorBody),
+        (status = 422, description = "Invalid body", body = ErrorBody),
+    )
+)]
+pub async fn patch(
+    Extension(state): Extension<Arc<Context>>,
+    _user: ApiUser,
+    scope: Scope,
+    headers: HeaderMap,
+    Path(id): Path<u32>,
+    Body(input): Body<VetPatch>,
+) -> Result<Response, ApiError> {
+    let mut vet = find(&state, &scope, id).await?;
+    version_of(&vet).check_if_match(&headers, "vet")?;
+    if let Some(name) = input.name {
+        vet.name = name;
+    }
+
+    update_vet(&state, vet).await
+}
+
+/// Vets with pets or visits are not removed, they go through the reassignment wizard
+#[utoipa::path(
+    delete,
+    path = "/api/v1/vets/{id}",
+    tag = "vets",
+    params(
+        ("id" = u32, Path, description = "Vet id"),
+        ("If-Match" = Option<String>, Header, description = "ETag of the version the change is based on"),
+    ),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+        (status = 404, description = "Unknown vet", body = ErrorBody),
+        (status = 412, description = "Changed since the If-Match version", body = ErrorBody),
+        (status = 409, description = "The vet still has pets or visits, counted in `details.dependents`", body = ErrorBody),
+    )
+)]
+pub async fn delete(
+    Extension(state): Extension<Arc<Context>>,
+    _user: ApiUser,
+    scope: Scope,
+    headers: HeaderMap,
+    Path(id): Path<u32>,
+) -> Result<StatusCode, ApiError> {
+    let vet = find(&state, &scope, id).await?;
+    version_of(&vet).check_if_match(&headers, "vet")?;
+    let dependents = vets::dependents(&state.rb, &vet).await?;
+    if !dependents.is_empty() {
+        return Err(ApiError::conflict(
+            "the vet still has pets or visits, reassign them first",
+            json!({ "dependents": dependents }),
+        ));
+    }
+    vets::delete(&state.rb, &vet).await?;
+    events::publish(&state, Change::vet(Action::Deleted, &vet));
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,254 @@
+use crate::{
+    handlers::api::{ApiError, ApiUser, Body, PageBody, Validation},
+    logic::{
+        clinics::Scope,
+        paging::Paging,
+        pets, vets,
+        visits::{self, Visit},
+        webhooks,
+    },
+    Context,
+};
+use axum::{
+    extract::{Extension, Json, Path, Query},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+
+use chrono::naive::NaiveDateTime;
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+use std::{collections::HashMap, sync::Arc};
+
+#[derive(Deserialize, ToSchema)]
+pub struct VisitInput {
+    pet_id: u32,
+    vet_id: u32,
+    visit_date: NaiveDateTime,
+    #[serde(default)]
+    notes: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct VisitPatch {
+    pet_id: Option<u32>,
+    vet_id: Option<u32>,
+    visit_date: Option<NaiveDateTime>,
+    /// null removes the notes
+    #[serde(default, deserialize_with = "super::nullable")]
+    #[schema(value_type = Option<String>, nullable)]
+    notes: Option<Option<String>>,
+}
+
+/// The pet must be visible to the caller and the vet work at the pet's clinic, returns the
+/// clinic of the visit. New visits, and visits moved to another vet, need an active vet; the
+/// `current` version of a visit keeps its vet even once deactivated.
+async fn validate(
+    state: &Context,
+    scope: &Scope,
+    visit: &Visit,
+    current: Option<&Visit>,
+) -> Result<u32, ApiError> {
+    let mut v = Validation::default();
+    let pet = pets::get(&state.rb, scope, visit.pet_id).await?;
+    match &pet {
+        Some(pet) => {
+            let vet = vets::get(&state.rb, &Scope::Clinic(pet.clinic_id), visit.vet_id).await?;
+            let same_vet = current.is_some_and(|c| c.vet_id == visit.vet_id);
+            match vet {
+                Some(vet) => v.check(
+                    vet.active || same_vet,
+                    "vet_id",
+                    "is not an active vet of the pet's clinic",
+                ),
+                None => v.error("vet_id", "is not a vet of the pet's clinic"),
+            }
+        }
+        None => v.error("pet_id", "is not a known pet"),
+    }
+    v.result()?;
+
+    Ok(pet.map(|p| p.clinic_id).unwrap_or_default())
+}
+
+async fn find(state: &Context, scope: &Scope, id: u32) -> Result<Visit, ApiError> {
+    visits::get(&state.rb, scope, id)
+        .await?
+        .ok_or_else(|| ApiError::not_found("visit"))
+}
+
+/// Filtered by `pet_id` and `vet_id` when given
+#[utoipa::path(
+    get,
+    path = "/api/v1/visits",
+    tag = "visits",
+    params(
+        ("pet_id" = Option<u32>, Query, description = "Visits of this pet only"),
+        ("vet_id" = Option<u32>, Query, description = "Visits with this vet only"),
+        Paging),
+    responses(
+        (status = 200, description = "One page of visits", body = VisitPage),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+        (status = 422, description = "Invalid filters", body = ErrorBody),
+    )
+)]
+pub async fn list(
+    Extension(state): Extension<Arc<Context>>,
+    _user: ApiUser,
+    scope: Scope,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<PageBody<Visit>>, ApiError> {
+    let id = |key: &str| params.get(key).and_then(|v| v.parse().ok());
+    let paging = Paging::from_query(&params, visits::SORTABLE);
+    let page = visits::search(&state.rb, &scope, id("pet_id"), id("vet_id"), &paging).await?;
+
+    Ok(Json(page.into()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/visits/{id}",
+    tag = "visits",
+    params(("id" = u32, Path, description = "Visit id")),
+    responses(
+        (status = 200, description = "The visit", body = Visit),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+        (status = 404, description = "Unknown visit", body = ErrorBody),
+    )
+)]
+pub async fn get(
+    Extension(state): Extension<Arc<Context>>,
+    _user: ApiUser,
+    scope: Scope,
+    Path(id): Path<u32>,
+) -> Result<Json<Visit>, ApiError> {
+    Ok(Json(find(&state, &scope, id).await?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/visits",
+    tag = "visits",
+    request_body = VisitInput,
+    responses(
+        (status = 201, description = "Created, its url is in the Location header", body = Visit),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+        (status = 422, description = "Invalid body", body = ErrorBody),
+    )
+)]
+pub async fn create(
+    Extension(state): Extension<Arc<Context>>,
+    _user: ApiUser,
+    scope: Scope,
+    Body(input): Body<VisitInput>,
+) -> Result<Response, ApiError> {
+    let mut visit = Visit {
+        id: 0,
+        pet_id: input.pet_id,
+        vet_id: input.vet_id,
+        visit_date: input.visit_date,
+        notes: input.notes,
+    };
+    let clinic_id = validate(&state, &scope, &visit, None).await?;
+    visit.id = visits::save(&state.rb, &visit).await?;
+    webhooks::publish(&state.rb, clinic_id, webhooks::VISIT_CREATED, &visit).await;
+
+    Ok((
+        StatusCode::CREATED,
+        [(header::LOCATION, format!("/api/v1/visits/{}", visit.id))],
+        Json(visit),
+    )
+        .into_response())
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/visits/{id}",
+    tag = "visits",
+    params(("id" = u32, Path, description = "Visit id")),
+    request_body = VisitInput,
+    responses(
+        (status = 200, description = "Replaced", body = Visit),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+        (status = 404, description = "Unknown visit", body = ErrorBody),
+        (status = 422, description = "Invalid body", body = ErrorBody),
+    )
+)]
+pub async fn update(
+    Extension(state): Extension<Arc<Context>>,
+    _user: ApiUser,
+    scope: Scope,
+    Path(id): Path<u32>,
+    Body(input): Body<VisitInput>,
+) -> Result<Json<Visit>, ApiError> {
+    let current = find(&state, &scope, id).await?;
+    let visit = Visit {
+        id: current.id,
+        pet_id: input.pet_id,
+        vet_id: input.vet_id,
+        visit_date: input.visit_date,
+        notes: input.notes,
+    };
+    validate(&state, &scope, &visit, Some(&current)).await?;
+    visits::save(&state.rb, &visit).await?;
+
+    Ok(Json(visit))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/v1/visits/{id}",
+    tag = "visits",
+    params(("id" = u32, Path, description = "Visit id")),
+    request_body = VisitPatch,
+    responses(
+        (status = 200, description = "Updated", body = Visit),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+        (status = 404, description = "Unknown visit", body = ErrorBody),
+        (status = 422, description = "Invalid body", body = ErrorBody),
+    )
+)]
+pub async fn patch(
+    Extension(state): Extension<Arc<Context>>,
+    _user: ApiUser,
+    scope: Scope,
+    Path(id): Path<u32>,
+    Body(input): Body<VisitPatch>,
+) -> Result<Json<Visit>, ApiError> {
+    let current = find(&state, &scope, id).await?;
+    let visit = Visit {
+        id: current.id,
+        pet_id: input.pet_id.unwrap_or(current.pet_id),
+        vet_id: input.vet_id.unwrap_or(current.vet_id),
+        visit_date: input.visit_date.unwrap_or(current.visit_date),
+        notes: input.notes.unwrap_or_else(|| current.notes.clone()),
+    };
+    validate(&state, &scope, &visit, Some(&current)).await?;
+    visits::save(&state.rb, &visit).await?;
+
+    Ok(Json(visit))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/visits/{id}",
+    tag = "visits",
+    params(("id" = u32, Path, description = "Visit id")),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+        (status = 404, description = "Unknown visit", body = ErrorBody),
+    )
+)]
+pub async fn delete(
+    Extension(state): Extension<Arc<Context>>,
+    _user: ApiUser,
+    scope: Scope,
+    Path(id): Path<u32>,
+) -> Result<StatusCode, ApiError> {
+    let visit = find(&state, &scope, id).await?;
+    visits::delete(&state.rb, &visit).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
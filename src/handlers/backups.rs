@@ -0,0 +1,63 @@
+use crate::{
+    logic::{backup, clinics::ActiveClinic, users::User},
+    AppError, Context,
+};
+use axum::{
+    extract::{Extension, Query},
+    http::header,
+    response::{Html, IntoResponse, Redirect, Response},
+};
+
+use serde::Deserialize;
+use tera::Tera;
+
+use std::{error::Error, sync::Arc};
+
+#[derive(Deserialize)]
+pub struct DownloadParams {
+    #[serde(default)]
+    passwords: bool,
+}
+
+pub async fn page(
+    Extension(tera): Extension<Tera>,
+    Extension(clinic): Extension<ActiveClinic>,
+    _user: User,
+) -> Result<Response, AppError> {
+    if !clinic.is_admin {
+        return Ok(Redirect::to("/").into_response());
+    }
+
+    let mut c = tera::Context::new();
+    c.insert("version", &backup::FORMAT_VERSION);
+    let r = tera.render("backup/index.html", &c).unwrap();
+
+    Ok(Html::from(r).into_response())
+}
+
+/// The whole database as a JSON archive, only for admins as it spans every clinic
+pub async fn download(
+    Extension(state): Extension<Arc<Context>>,
+    Extension(clinic): Extension<ActiveClinic>,
+    _user: User,
+    Query(params): Query<DownloadParams>,
+) -> Result<Response, AppError> {
+    if !clinic.is_admin {
+        return Ok(Redirect::to("/").into_response());
+    }
+
+    let archive = backup::create(&state.rb, params.passwords).await?;
+    let body = serde_json::to_vec_pretty(&archive).map_err(|e| Box::new(e) as Box<dyn Error>)?;
+    let headers = [
+        (header::CONTENT_TYPE, "application/json".to_string()),
+        (
+            header::CONTENT_DISPOSITION,
+            format!(
+                "attachment; filename=\"petclinic-backup-{}.json\"",
+                archive.created_at.format("%Y%m%d-%H%M")
+            ),
+        ),
+    ];
+
+    Ok((headers, body).into_response())
+}
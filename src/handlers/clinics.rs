@@ -0,0 +1,48 @@
+use crate::{
+    logic::{clinics, users::User},
+    AppError, Context,
+};
+use axum::{
+    extract::Extension,
+    response::{IntoResponse, Redirect},
+};
+use axum_extra::extract::{CookieJar, Form};
+use redis::{Commands, RedisError};
+use serde::Deserialize;
+
+use std::sync::Arc;
+
+#[derive(Deserialize)]
+pub struct SwitchForm {
+    clinic_id: u32,
+}
+
+pub async fn switch(
+    Extension(state): Extension<Arc<Context>>,
+    user: User,
+    jar: CookieJar,
+    form: Form<SwitchForm>,
+) -> Result<impl IntoResponse, AppError> {
+    if !clinics::can_access(&state.rb, &user, form.clinic_id).await? {
+        tracing::warn!(
+            "User {} tried to switch to clinic {}",
+            user.username,
+            form.clinic_id
+        );
+        return Ok(Redirect::to("/pets"));
+    }
+
+    if let Some(session) = jar.get("axum_session") {
+        let mut conn = state.redis_connection.lock().unwrap();
+        let redis_response: Result<(), RedisError> = conn.set_ex(
+            clinics::redis_key(session.value()),
+            form.clinic_id,
+            state.env.session_timeout,
+        );
+        if let Err(e) = redis_response {
+            tracing::error!("Cannot write into redis: {}", e);
+        }
+    }
+
+    Ok(Redirect::to("/pets"))
+}
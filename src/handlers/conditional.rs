@@ -0,0 +1,123 @@
+//! `ETag` and `Last-Modified` of the pet and vet details, and the conditional requests
+//! checked against them: `If-None-Match`/`If-Modified-Since` answer 304 to clients whose
+//! copy is current, `If-Match` refuses API changes made from an outdated copy with 412.
+
+use axum::{
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, NaiveDateTime, Timelike};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::handlers::{api::ApiError, negotiate::Accept};
+
+/// Format of the dates in HTTP headers, always in GMT
+const HTTP_DATE: &str = "%a, %d %b %Y %H:%M:%S GMT";
+/// Bytes of the SHA-256 of the body kept in the tags
+const TAG_BYTES: usize = 12;
+
+/// Validators of the current version of a resource
+pub struct Version {
+    etag: String,
+    last_modified: Option<NaiveDateTime>,
+}
+
+impl Version {
+    /// Version of `data`, what the response is made of. The tags of the HTML pages are weak,
+    /// their markup also depends on the templates.
+    pub fn of<T: Serialize>(
+        data: &T,
+        accept: Accept,
+        last_modified: Option<NaiveDateTime>,
+    ) -> Version {
+        let json = serde_json::to_vec(data).unwrap_or_default();
+        let hash: String = Sha256::digest(&json)[..TAG_BYTES]
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+
+        Version {
+            etag: match accept {
+                Accept::Json => format!("\"{}\"", hash),
+                Accept::Html => format!("W/\"h{}\"", hash),
+            },
+            last_modified: last_modified.and_then(|t| t.with_nanosecond(0)),
+        }
+    }
+
+    /// Whether the client's copy is current, by `If-None-Match` or else `If-Modified-Since`
+    fn is_fresh(&self, headers: &HeaderMap) -> bool {
+        if let Some(tags) = header_text(headers, header::IF_NONE_MATCH) {
+            // weak comparison
+            return any_tag(tags, |tag| opaque(tag) == opaque(&self.etag));
+        }
+
+        let since = header_text(headers, header::IF_MODIFIED_SINCE)
+            .and_then(|date| DateTime::parse_from_rfc2822(date).ok());
+        match (since, self.last_modified) {
+            (Some(since), Some(modified)) => modified <= since.naive_utc(),
+            _ => false,
+        }
+    }
+
+    /// Refuses a change when `If-Match` names another version than this one
+    pub fn check_if_match(&self, headers: &HeaderMap, what: &str) -> Result<(), ApiError> {
+        match header_text(headers, header::IF_MATCH) {
+            // strong comparison, weak tags never match
+            Some(tags) if !any_tag(tags, |tag| !tag.starts_with("W/") && tag == self.etag) => {
+                Err(ApiError::new(
+                    StatusCode::PRECONDITION_FAILED,
+                    &format!("the {} has changed since it was read", what),
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// `response` with the validators, or an empty 304 when the client's copy is current
+    pub fn respond(&self, headers: &HeaderMap, response: Response) -> Response {
+        if !self.is_fresh(headers) {
+            return self.apply(response);
+        }
+
+        let mut not_modified = StatusCode::NOT_MODIFIED.into_response();
+        if let Some(vary) = response.headers().get(header::VARY) {
+            not_modified
+                .headers_mut()
+                .insert(header::VARY, vary.clone());
+        }
+        self.apply(not_modified)
+    }
+
+    /// Adds the `ETag` and `Last-Modified` headers to `response`
+    pub fn apply(&self, mut response: Response) -> Response {
+        let headers = response.headers_mut();
+        if let Ok(etag) = HeaderValue::from_str(&self.etag) {
+            headers.insert(header::ETAG, etag);
+        }
+        if let Some(modified) = self.last_modified {
+            if let Ok(date) = HeaderValue::from_str(&modified.format(HTTP_DATE).to_string()) {
+                headers.insert(header::LAST_MODIFIED, date);
+            }
+        }
+
+        response
+    }
+}
+
+fn header_text(headers: &HeaderMap, name: header::HeaderName) -> Option<&str> {
+    headers.get(name).and_then(|v| v.to_str().ok())
+}
+
+/// Whether `*` or any tag of the comma separated `tags` satisfies `matches`
+fn any_tag(tags: &str, matches: impl Fn(&str) -> bool) -> bool {
+    tags.split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || matches(tag))
+}
+
+/// Tag without its weakness indicator
+fn opaque(tag: &str) -> &str {
+    tag.strip_prefix("W/").unwrap_or(tag)
+}
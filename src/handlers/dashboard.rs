@@ -0,0 +1,35 @@
+use crate::{
+    logic::{clinics::Scope, stats, users::User},
+    AppError, Context,
+};
+use axum::{extract::Extension, response::Html};
+use tera::Tera;
+
+use std::sync::Arc;
+
+pub async fn dashboard(
+    Extension(tera): Extension<Tera>,
+    Extension(state): Extension<Arc<Context>>,
+    _user: User,
+    scope: Scope,
+) -> Result<Html<String>, AppError> {
+    let mut c = tera::Context::new();
+
+    c.insert(
+        "pets_by_type",
+        &stats::pets_by_type(&state.rb, &scope).await?,
+    );
+    c.insert(
+        "pets_per_vet",
+        &stats::pets_per_vet(&state.rb, &scope).await?,
+    );
+    c.insert(
+        "registrations",
+        &stats::registrations_per_month(&state.rb, &scope).await?,
+    );
+    c.insert("visits", &stats::visits_per_week(&state.rb, &scope).await?);
+    c.insert("all_clinics", &scope.is_all());
+    let r = tera.render("dashboard.html", &c).unwrap();
+
+    Ok(Html::from(r))
+}
@@ -0,0 +1,44 @@
+use crate::{
+    logic::{clinics::Scope, events::Change},
+    Context,
+};
+use axum::{
+    extract::Extension,
+    response::sse::{Event, KeepAlive, Sse},
+};
+use futures::{stream, Stream};
+use tokio::sync::broadcast::error::RecvError;
+
+use std::sync::Arc;
+
+fn is_visible(scope: &Scope, change: &Change) -> bool {
+    match scope {
+        Scope::Clinic(id) => change.clinic_id == *id,
+        Scope::All => true,
+    }
+}
+
+/// Server-Sent Events stream of the pet and vet changes of the scope. Each change is a
+/// `pet` or `vet` event with the `Change` as JSON; `resync` tells the page it missed some.
+pub async fn stream(
+    Extension(state): Extension<Arc<Context>>,
+    scope: Scope,
+) -> Sse<impl Stream<Item = Result<Event, serde_json::Error>>> {
+    let receiver = state.changes.subscribe();
+
+    let changes = stream::unfold(receiver, move |mut receiver| async move {
+        loop {
+            let event = match receiver.recv().await {
+                Ok(change) if is_visible(&scope, &change) => Event::default()
+                    .event(change.kind.as_str())
+                    .json_data(&change),
+                Ok(_) => continue,
+                Err(RecvError::Lagged(_)) => Ok(Event::default().event("resync").data("")),
+                Err(RecvError::Closed) => return None,
+            };
+            return Some((event, receiver));
+        }
+    });
+
+    Sse::new(changes).keep_alive(KeepAlive::default())
+}
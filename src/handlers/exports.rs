@@ -0,0 +1,269 @@
+use crate::{
+    logic::{
+        clinics::Scope,
+        export::{self, Column, Format},
+        paging::Paging,
+        pets::{self, PetFilter},
+        users::User,
+        vets, visits,
+    },
+    AppError, Context,
+};
+use axum::{
+    body::StreamBody,
+    extract::{Extension, Query},
+    http::header,
+    response::{IntoResponse, Response},
+};
+
+use futures::{stream, Future};
+use serde::Deserialize;
+
+use std::{collections::HashMap, error::Error, io, sync::Arc};
+
+/// Rows are read from the database by pages of this size while streaming
+const EXPORT_PAGE_SIZE: u64 = 500;
+
+type Rows = Vec<Vec<String>>;
+
+#[derive(Deserialize)]
+pub struct ExportParams {
+    #[serde(default)]
+    format: String,
+    /// keys of the columns to export, all of them when empty
+    #[serde(default)]
+    columns: Vec<String>,
+}
+
+/// Same ordering as the list view, but with large pages
+fn export_paging(params: &HashMap<String, String>, sortable: &[&str], page: u64) -> Paging {
+    Paging {
+        page,
+        page_size: EXPORT_PAGE_SIZE,
+        ..Paging::from_query(params, sortable)
+    }
+}
+
+/// Sends the rows returned page by page by `next_page` as a file download.
+/// `next_page` also tells whether more pages follow.
+/// CSV is streamed while it is read, XLSX has to be built in memory.
+async fn download<F, Fut>(
+    name: &str,
+    format: Format,
+    columns: Vec<&'static Column>,
+    mut next_page: F,
+) -> Result<Response, AppError>
+where
+    F: FnMut(u64) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<(Rows, bool), rbatis::Error>> + Send + 'static,
+{
+    let headers = [
+        (header::CONTENT_TYPE, format.content_type().to_string()),
+        (
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}.{}\"", name, format.extension()),
+        ),
+    ];
+
+    if format == Format::Xlsx {
+        let mut rows = Vec::new();
+        let mut page = 1;
+        loop {
+            let (mut page_rows, more) = next_page(page).await?;
+            rows.append(&mut page_rows);
+            if !more {
+                break;
+            }
+            page += 1;
+        }
+        let body =
+            export::xlsx(name, &columns, &rows).map_err(|e| Box::new(e) as Box<dyn Error>)?;
+
+        return Ok((headers, body).into_response());
+    }
+
+    let body = stream::unfold(Some((1, next_page)), move |state| {
+        let columns = columns.clone();
+        async move {
+            let (page, mut next_page) = state?;
+            let chunk = next_page(page)
+                .await
+                .map_err(io::Error::other)
+                .and_then(|(rows, more)| {
+                    let chunk = export::csv(&columns, &rows, page == 1)?;
+                    Ok((chunk, more))
+                });
+
+            match chunk {
+                Ok((chunk, true)) => Some((Ok(chunk), Some((page + 1, next_page)))),
+                Ok((chunk, false)) => Some((Ok(chunk), None)),
+                // the response has already started, stop the stream with the error
+                Err(e) => Some((Err(e), None)),
+            }
+        }
+    });
+
+    Ok((headers, StreamBody::new(body)).into_response())
+}
+
+pub async fn pets(
+    Extension(state): Extension<Arc<Context>>,
+    _user: User,
+    scope: Scope,
+    Query(params): Query<HashMap<String, String>>,
+    axum_extra::extract::Query(export_params): axum_extra::extract::Query<ExportParams>,
+) -> Result<Response, AppError> {
+    let columns = export::select(export::PET_COLUMNS, &export_params.columns);
+    let filter = Arc::new(PetFilter::from_query(&params));
+    let vet_names = Arc::new(vets::names(&state.rb, &scope).await?);
+    let params = Arc::new(params);
+
+    let row_columns = columns.clone();
+    let next_page = move |page| {
+        let (state, filter, vet_names, params, columns) = (
+            state.clone(),
+            filter.clone(),
+            vet_names.clone(),
+            params.clone(),
+            row_columns.clone(),
+        );
+        async move {
+            if filter.query_error.is_some() {
+                return Ok((Vec::new(), false));
+            }
+            let paging = export_paging(&params, pets::SORTABLE, page);
+            let result = pets::search(&state.rb, &scope, &filter, &paging).await?;
+            let rows = result
+                .records
+                .iter()
+                .map(|pet| export::pet_row(pet, &columns, &vet_names))
+                .collect();
+
+            Ok((rows, result.page_no < result.pages))
+        }
+    };
+
+    download(
+        "pets",
+        Format::from_param(&export_params.format),
+        columns,
+        next_page,
+    )
+    .await
+}
+
+/// The pets picked on the list, in the order they were picked
+pub async fn selected_pets(
+    state: Arc<Context>,
+    scope: Scope,
+    ids: Vec<u32>,
+    format: Format,
+) -> Result<Response, AppError> {
+    let columns = export::select(export::PET_COLUMNS, &[]);
+    let vet_names = Arc::new(vets::names(&state.rb, &scope).await?);
+    let ids = Arc::new(ids);
+
+    let row_columns = columns.clone();
+    let next_page = move |_page| {
+        let (state, ids, vet_names, columns) = (
+            state.clone(),
+            ids.clone(),
+            vet_names.clone(),
+            row_columns.clone(),
+        );
+        async move {
+            let mut selected = pets::by_ids(&state.rb, &scope, &ids).await?;
+            selected.sort_by_key(|pet| ids.iter().position(|id| *id == pet.id));
+            let rows = selected
+                .iter()
+                .map(|pet| export::pet_row(pet, &columns, &vet_names))
+                .collect();
+
+            // a selection fits in one page of the list
+            Ok((rows, false))
+        }
+    };
+
+    download("pets", format, columns, next_page).await
+}
+
+pub async fn vets(
+    Extension(state): Extension<Arc<Context>>,
+    _user: User,
+    scope: Scope,
+    Query(params): Query<HashMap<String, String>>,
+    axum_extra::extract::Query(export_params): axum_extra::extract::Query<ExportParams>,
+) -> Result<Response, AppError> {
+    let columns = export::select(export::VET_COLUMNS, &export_params.columns);
+    let params = Arc::new(params);
+
+    let row_columns = columns.clone();
+    let next_page = move |page| {
+        let (state, params, columns) = (state.clone(), params.clone(), row_columns.clone());
+        async move {
+            let paging = export_paging(&params, vets::SORTABLE, page);
+            let result = vets::search(&state.rb, &scope, params.get("name"), &paging).await?;
+            let rows = result
+                .records
+                .iter()
+                .map(|vet| export::vet_row(vet, &columns))
+                .collect();
+
+            Ok((rows, result.page_no < result.pages))
+        }
+    };
+
+    download(
+        "vets",
+        Format::from_param(&export_params.format),
+        columns,
+        next_page,
+    )
+    .await
+}
+
+/// Visits of the pets matching the pet list filters
+pub async fn visits(
+    Extension(state): Extension<Arc<Context>>,
+    _user: User,
+    scope: Scope,
+    Query(params): Query<HashMap<String, String>>,
+    axum_extra::extract::Query(export_params): axum_extra::extract::Query<ExportParams>,
+) -> Result<Response, AppError> {
+    let columns = export::select(export::VISIT_COLUMNS, &export_params.columns);
+    let filter = Arc::new(PetFilter::from_query(&params));
+    let params = Arc::new(params);
+
+    let row_columns = columns.clone();
+    let next_page = move |page| {
+        let (state, filter, params, columns) = (
+            state.clone(),
+            filter.clone(),
+            params.clone(),
+            row_columns.clone(),
+        );
+        async move {
+            if filter.query_error.is_some() {
+                return Ok((Vec::new(), false));
+            }
+            let paging = export_paging(&params, pets::SORTABLE, page);
+            let result = pets::search(&state.rb, &scope, &filter, &paging).await?;
+            let pet_ids: Vec<u32> = result.records.iter().map(|p| p.id).collect();
+            let rows = visits::of_pets(&state.rb, &pet_ids)
+                .await?
+                .iter()
+                .map(|visit| export::visit_row(visit, &columns))
+                .collect();
+
+            Ok((rows, result.page_no < result.pages))
+        }
+    };
+
+    download(
+        "visits",
+        Format::from_param(&export_params.format),
+        columns,
+        next_page,
+    )
+    .await
+}
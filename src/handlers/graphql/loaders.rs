@@ -0,0 +1,131 @@
+use crate::{
+    handlers::graphql::types::MAX_NESTED,
+    logic::{
+        clinics::Scope,
+        pets::{self, Pet},
+        users::{self, User},
+        vets::{self, Vet},
+        visits::{self, Visit},
+    },
+    Context,
+};
+use async_graphql::{dataloader::Loader, Error};
+
+use std::{collections::HashMap, sync::Arc};
+
+#[derive(Clone, Copy, Hash, PartialEq, Eq)]
+pub struct PetId(pub u32);
+
+#[derive(Clone, Copy, Hash, PartialEq, Eq)]
+pub struct VetId(pub u32);
+
+#[derive(Clone, Copy, Hash, PartialEq, Eq)]
+pub struct UserId(pub u32);
+
+/// Visits of a pet, latest first, at most `MAX_NESTED`
+#[derive(Clone, Copy, Hash, PartialEq, Eq)]
+pub struct VisitsOf(pub u32);
+
+/// Pets cared for by a vet, the first `MAX_NESTED` by name
+#[derive(Clone, Copy, Hash, PartialEq, Eq)]
+pub struct PetsOf(pub u32);
+
+/// Hides database errors from the client, they only go to the log
+pub fn internal(e: rbatis::Error) -> Error {
+    tracing::error!("GraphQL database error: {}", e);
+    Error::new("internal error")
+}
+
+/// Loads the records a query refers to in one statement per kind and level of the query,
+/// restricted to the scope of the request
+pub struct ClinicLoader {
+    pub state: Arc<Context>,
+    pub scope: Scope,
+}
+
+impl Loader<PetId> for ClinicLoader {
+    type Value = Pet;
+    type Error = Error;
+
+    async fn load(&self, keys: &[PetId]) -> Result<HashMap<PetId, Pet>, Error> {
+        let ids: Vec<u32> = keys.iter().map(|k| k.0).collect();
+        let pet_list = pets::by_ids(&self.state.rb, &self.scope, &ids)
+            .await
+            .map_err(internal)?;
+
+        Ok(pet_list.into_iter().map(|p| (PetId(p.id), p)).collect())
+    }
+}
+
+impl Loader<VetId> for ClinicLoader {
+    type Value = Vet;
+    type Error = Error;
+
+    async fn load(&self, keys: &[VetId]) -> Result<HashMap<VetId, Vet>, Error> {
+        let ids: Vec<u32> = keys.iter().map(|k| k.0).collect();
+        let vet_list = vets::by_ids(&self.state.rb, &self.scope, &ids)
+            .await
+            .map_err(internal)?;
+
+        Ok(vet_list.into_iter().map(|v| (VetId(v.id), v)).collect())
+    }
+}
+
+impl Loader<UserId> for ClinicLoader {
+    type Value = User;
+    type Error = Error;
+
+    async fn load(&self, keys: &[UserId]) -> Result<HashMap<UserId, User>, Error> {
+        let ids: Vec<u32> = keys.iter().map(|k| k.0).collect();
+        let user_list = users::by_ids(&self.state.rb, &ids)
+            .await
+            .map_err(internal)?;
+
+        Ok(user_list.into_iter().map(|u| (UserId(u.id), u)).collect())
+    }
+}
+
+/// Only asked for pets already loaded through the scope
+impl Loader<VisitsOf> for ClinicLoader {
+    type Value = Vec<Visit>;
+    type Error = Error;
+
+    async fn load(&self, keys: &[VisitsOf]) -> Result<HashMap<VisitsOf, Vec<Visit>>, Error> {
+        let ids: Vec<u32> = keys.iter().map(|k| k.0).collect();
+        let visit_list = visits::by_pets(&self.state.rb, &ids, MAX_NESTED)
+            .await
+            .map_err(internal)?;
+
+        let mut by_pet: HashMap<VisitsOf, Vec<Visit>> =
+            keys.iter().map(|k| (*k, Vec::new())).collect();
+        for visit in visit_list {
+            by_pet
+                .entry(VisitsOf(visit.pet_id))
+                .or_default()
+                .push(visit);
+        }
+
+        Ok(by_pet)
+    }
+}
+
+impl Loader<PetsOf> for ClinicLoader {
+    type Value = Vec<Pet>;
+    type Error = Error;
+
+    async fn load(&self, keys: &[PetsOf]) -> Result<HashMap<PetsOf, Vec<Pet>>, Error> {
+        let ids: Vec<u32> = keys.iter().map(|k| k.0).collect();
+        let pet_list = pets::of_vets(&self.state.rb, &self.scope, &ids, MAX_NESTED)
+            .await
+            .map_err(internal)?;
+
+        let mut by_vet: HashMap<PetsOf, Vec<Pet>> = keys.iter().map(|k| (*k, Vec::new())).collect();
+        for pet in pet_list {
+            if let Some(vet_id) = pet.vet_id {
+                by_vet.entry(PetsOf(vet_id)).or_default().push(pet);
+            }
+        }
+
+        Ok(by_vet)
+    }
+}
@@ -0,0 +1,204 @@
+//! GraphQL endpoint at `/graphql`, read only, over pets, vets, visits and users.
+//!
+//! Nested records are fetched through `loaders::ClinicLoader`, batching each level of a query
+//! into one statement. Queries deeper than `MAX_DEPTH` or costlier than `MAX_COMPLEXITY` are
+//! refused before running.
+
+pub mod loaders;
+pub mod types;
+
+use crate::{
+    handlers::api::{ApiError, ApiUser, Body},
+    logic::{
+        clinics::Scope,
+        paging::{Paging, DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE},
+        pets::{self, PetFilter},
+        users::User,
+        vets, visits,
+    },
+};
+use async_graphql::{
+    dataloader::DataLoader, Context, EmptyMutation, EmptySubscription, Error, Object, Request,
+    Response, Result, Schema,
+};
+use axum::{
+    extract::{Extension, Json, RawQuery},
+    http::StatusCode,
+};
+
+use loaders::{internal, ClinicLoader, PetId, VetId};
+use types::{PageOf, PetNode, UserNode, VetNode, VisitNode};
+
+use std::{collections::HashMap, sync::Arc};
+
+pub const MAX_DEPTH: usize = 8;
+pub const MAX_COMPLEXITY: usize = 2000;
+
+pub type ClinicSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+pub fn schema() -> ClinicSchema {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .limit_depth(MAX_DEPTH)
+        .limit_complexity(MAX_COMPLEXITY)
+        .finish()
+}
+
+/// Paging arguments as the list pages read them from their query string
+fn paging(page: Option<u64>, page_size: Option<u64>, sortable: &[&str]) -> Paging {
+    let mut params = HashMap::new();
+    if let Some(page) = page {
+        params.insert("page".to_string(), page.to_string());
+    }
+    if let Some(page_size) = page_size {
+        params.insert("page_size".to_string(), page_size.to_string());
+    }
+
+    Paging::from_query(&params, sortable)
+}
+
+fn state<'a>(ctx: &Context<'a>) -> (&'a crate::Context, &'a Scope) {
+    (
+        ctx.data_unchecked::<Arc<crate::Context>>(),
+        ctx.data_unchecked::<Scope>(),
+    )
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// The caller
+    async fn me(&self, ctx: &Context<'_>) -> UserNode {
+        UserNode(ctx.data_unchecked::<User>().clone())
+    }
+
+    async fn pet(&self, ctx: &Context<'_>, id: u32) -> Result<Option<PetNode>> {
+        let loader = ctx.data_unchecked::<DataLoader<ClinicLoader>>();
+
+        Ok(loader.load_one(PetId(id)).await?.map(PetNode))
+    }
+
+    /// Pets matching `q`, in the syntax of the pet list search box
+    #[graphql(
+        complexity = "page_size.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE) as usize * child_complexity"
+    )]
+    async fn pets(
+        &self,
+        ctx: &Context<'_>,
+        q: Option<String>,
+        page: Option<u64>,
+        page_size: Option<u64>,
+    ) -> Result<PageOf<PetNode>> {
+        let (state, scope) = state(ctx);
+        let mut params = HashMap::new();
+        if let Some(q) = q {
+            params.insert("q".to_string(), q);
+        }
+        let filter = PetFilter::from_query(&params);
+        if let Some(e) = &filter.query_error {
+            return Err(Error::new(e.to_string()));
+        }
+        let paging = paging(page, page_size, pets::SORTABLE);
+        let found = pets::search(&state.rb, scope, &filter, &paging)
+            .await
+            .map_err(internal)?;
+
+        Ok(PageOf::of(found, PetNode))
+    }
+
+    async fn vet(&self, ctx: &Context<'_>, id: u32) -> Result<Option<VetNode>> {
+        let loader = ctx.data_unchecked::<DataLoader<ClinicLoader>>();
+
+        Ok(loader.load_one(VetId(id)).await?.map(VetNode))
+    }
+
+    /// Active vets, optionally those whose name contains `name`
+    #[graphql(
+        complexity = "page_size.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE) as usize * child_complexity"
+    )]
+    async fn vets(
+        &self,
+        ctx: &Context<'_>,
+        name: Option<String>,
+        page: Option<u64>,
+        page_size: Option<u64>,
+    ) -> Result<PageOf<VetNode>> {
+        let (state, scope) = state(ctx);
+        let paging = paging(page, page_size, vets::SORTABLE);
+        let found = vets::search(&state.rb, scope, name.as_ref(), &paging)
+            .await
+            .map_err(internal)?;
+
+        Ok(PageOf::of(found, VetNode))
+    }
+
+    async fn visit(&self, ctx: &Context<'_>, id: u32) -> Result<Option<VisitNode>> {
+        let (state, scope) = state(ctx);
+        let visit = visits::get(&state.rb, scope, id).await.map_err(internal)?;
+
+        Ok(visit.map(VisitNode))
+    }
+
+    #[graphql(
+        complexity = "page_size.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE) as usize * child_complexity"
+    )]
+    async fn visits(
+        &self,
+        ctx: &Context<'_>,
+        pet_id: Option<u32>,
+        vet_id: Option<u32>,
+        page: Option<u64>,
+        page_size: Option<u64>,
+    ) -> Result<PageOf<VisitNode>> {
+        let (state, scope) = state(ctx);
+        let paging = paging(page, page_size, visits::SORTABLE);
+        let found = visits::search(&state.rb, scope, pet_id, vet_id, &paging)
+            .await
+            .map_err(internal)?;
+
+        Ok(PageOf::of(found, VisitNode))
+    }
+}
+
+async fn execute(
+    schema: &ClinicSchema,
+    state: Arc<crate::Context>,
+    user: User,
+    scope: Scope,
+    request: Request,
+) -> Json<Response> {
+    let loader = DataLoader::new(
+        ClinicLoader {
+            state: state.clone(),
+            scope,
+        },
+        tokio::spawn,
+    );
+    let request = request.data(state).data(user).data(scope).data(loader);
+
+    Json(schema.execute(request).await)
+}
+
+pub async fn post(
+    Extension(schema): Extension<ClinicSchema>,
+    Extension(state): Extension<Arc<crate::Context>>,
+    ApiUser(user): ApiUser,
+    scope: Scope,
+    Body(request): Body<Request>,
+) -> Json<Response> {
+    execute(&schema, state, user, scope, request).await
+}
+
+/// `query`, `variables` and `operationName` in the query string, usable with read only tokens
+pub async fn get(
+    Extension(schema): Extension<ClinicSchema>,
+    Extension(state): Extension<Arc<crate::Context>>,
+    ApiUser(user): ApiUser,
+    scope: Scope,
+    RawQuery(query): RawQuery,
+) -> Result<Json<Response>, ApiError> {
+    let request = async_graphql::http::parse_query_string(&query.unwrap_or_default())
+        .map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, &e.to_string()))?;
+
+    Ok(execute(&schema, state, user, scope, request).await)
+}
@@ -0,0 +1,205 @@
+use crate::{
+    handlers::graphql::loaders::{ClinicLoader, PetId, PetsOf, UserId, VetId, VisitsOf},
+    logic::{
+        pets::{self, Pet},
+        users::User,
+        vets::Vet,
+        visits::Visit,
+    },
+};
+use async_graphql::{dataloader::DataLoader, Context, Object, OutputType, Result, SimpleObject};
+use chrono::naive::NaiveDateTime;
+
+/// Most visits or pets a nested list returns
+pub const MAX_NESTED: usize = 50;
+
+fn loader<'a>(ctx: &Context<'a>) -> &'a DataLoader<ClinicLoader> {
+    ctx.data_unchecked::<DataLoader<ClinicLoader>>()
+}
+
+#[derive(SimpleObject)]
+#[graphql(concrete(name = "PetPage", params(PetNode)))]
+#[graphql(concrete(name = "VetPage", params(VetNode)))]
+#[graphql(concrete(name = "VisitPage", params(VisitNode)))]
+pub struct PageOf<T: OutputType> {
+    pub items: Vec<T>,
+    pub page: u64,
+    pub page_size: u64,
+    pub total: u64,
+    pub pages: u64,
+}
+
+impl<T: OutputType> PageOf<T> {
+    pub fn of<R>(page: rbatis::plugin::page::Page<R>, node: fn(R) -> T) -> PageOf<T> {
+        PageOf {
+            items: page.records.into_iter().map(node).collect(),
+            page: page.page_no,
+            page_size: page.page_size,
+            total: page.total,
+            pages: page.pages,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct Owner {
+    pub name: String,
+    pub phone: String,
+}
+
+pub struct PetNode(pub Pet);
+
+#[Object(name = "Pet")]
+impl PetNode {
+    async fn id(&self) -> u32 {
+        self.0.id
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    async fn age(&self) -> u32 {
+        self.0.age
+    }
+
+    async fn pet_type(&self) -> String {
+        pets::type_label(self.0.pet_type)
+    }
+
+    async fn owner(&self) -> Owner {
+        Owner {
+            name: self.0.owner_name.clone(),
+            phone: self.0.owner_phone.clone(),
+        }
+    }
+
+    async fn clinic_id(&self) -> u32 {
+        self.0.clinic_id
+    }
+
+    async fn created_at(&self) -> NaiveDateTime {
+        self.0.created_at
+    }
+
+    async fn created_by(&self, ctx: &Context<'_>) -> Result<Option<UserNode>> {
+        let user = loader(ctx).load_one(UserId(self.0.created_by)).await?;
+
+        Ok(user.map(UserNode))
+    }
+
+    async fn vet(&self, ctx: &Context<'_>) -> Result<Option<VetNode>> {
+        let vet = match self.0.vet_id {
+            Some(id) => loader(ctx).load_one(VetId(id)).await?,
+            None => None,
+        };
+
+        Ok(vet.map(VetNode))
+    }
+
+    /// The `last` visits, latest first
+    #[graphql(complexity = "last.min(MAX_NESTED) * child_complexity")]
+    async fn visits(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 5)] last: usize,
+    ) -> Result<Vec<VisitNode>> {
+        let visits = loader(ctx).load_one(VisitsOf(self.0.id)).await?;
+
+        Ok(visits
+            .unwrap_or_default()
+            .into_iter()
+            .take(last.min(MAX_NESTED))
+            .map(VisitNode)
+            .collect())
+    }
+}
+
+pub struct VetNode(pub Vet);
+
+#[Object(name = "Vet")]
+impl VetNode {
+    async fn id(&self) -> u32 {
+        self.0.id
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    async fn active(&self) -> bool {
+        self.0.active
+    }
+
+    async fn clinic_id(&self) -> u32 {
+        self.0.clinic_id
+    }
+
+    /// The first `first` pets by name
+    #[graphql(complexity = "first.min(MAX_NESTED) * child_complexity")]
+    async fn pets(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 20)] first: usize,
+    ) -> Result<Vec<PetNode>> {
+        let pets = loader(ctx).load_one(PetsOf(self.0.id)).await?;
+
+        Ok(pets
+            .unwrap_or_default()
+            .into_iter()
+            .take(first.min(MAX_NESTED))
+            .map(PetNode)
+            .collect())
+    }
+}
+
+pub struct VisitNode(pub Visit);
+
+#[Object(name = "Visit")]
+impl VisitNode {
+    async fn id(&self) -> u32 {
+        self.0.id
+    }
+
+    async fn visit_date(&self) -> NaiveDateTime {
+        self.0.visit_date
+    }
+
+    async fn notes(&self) -> Option<&str> {
+        self.0.notes.as_deref()
+    }
+
+    async fn pet(&self, ctx: &Context<'_>) -> Result<Option<PetNode>> {
+        let pet = loader(ctx).load_one(PetId(self.0.pet_id)).await?;
+
+        Ok(pet.map(PetNode))
+    }
+
+    async fn vet(&self, ctx: &Context<'_>) -> Result<Option<VetNode>> {
+        let vet = loader(ctx).load_one(VetId(self.0.vet_id)).await?;
+
+        Ok(vet.map(VetNode))
+    }
+}
+
+/// A staff member, without credentials
+pub struct UserNode(pub User);
+
+#[Object(name = "User")]
+impl UserNode {
+    async fn id(&self) -> u32 {
+        self.0.id
+    }
+
+    async fn username(&self) -> &str {
+        &self.0.username
+    }
+
+    async fn clinic_id(&self) -> u32 {
+        self.0.clinic_id
+    }
+
+    async fn is_admin(&self) -> bool {
+        self.0.is_admin
+    }
+}
@@ -1,5 +1,8 @@
-use crate::Context;
-use axum::{extract::Extension, response::Html};
+use crate::{logic::users::User, Context};
+use axum::{
+    extract::Extension,
+    response::{Html, IntoResponse, Redirect, Response},
+};
 use tera::Tera;
 
 use std::sync::Arc;
@@ -7,12 +10,18 @@ use std::sync::Arc;
 pub async fn home(
     Extension(tera): Extension<Tera>,
     Extension(_state): Extension<Arc<Context>>,
-) -> Html<String> {
+    user: Option<User>,
+) -> Response {
+    // logged in staff land on the dashboard
+    if user.is_some() {
+        return Redirect::to("/dashboard").into_response();
+    }
+
     let c = tera::Context::new();
 
     tracing::debug!("Main request");
 
     let r = tera.render("home.html", &c).unwrap();
 
-    Html::from(r)
+    Html::from(r).into_response()
 }
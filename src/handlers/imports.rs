@@ -0,0 +1,182 @@
+use crate::{
+    logic::{
+        clinics::ActiveClinic,
+        import::{self, Kind, Mapping, Upload},
+        users::User,
+    },
+    AppError, Context,
+};
+use axum::{
+    extract::{Extension, Multipart},
+    http::header,
+    response::{Html, IntoResponse, Response},
+};
+
+use axum_extra::extract::Form;
+use tera::Tera;
+
+use std::{collections::HashMap, error::Error, sync::Arc};
+
+/// Rows shown in the mapping step to check the columns
+const PREVIEW_ROWS: usize = 5;
+
+pub async fn upload(
+    Extension(tera): Extension<Tera>,
+    _user: User,
+) -> Result<Html<String>, AppError> {
+    let mut c = tera::Context::new();
+    c.insert("kind", &Kind::Pets);
+    let r = tera.render("import/upload.html", &c).unwrap();
+
+    Ok(Html::from(r))
+}
+
+/// Reads the uploaded file and asks which column holds each field
+pub async fn post_upload(
+    Extension(tera): Extension<Tera>,
+    _user: User,
+    mut multipart: Multipart,
+) -> Result<Html<String>, AppError> {
+    let mut kind = Kind::Pets;
+    let mut content = String::new();
+    loop {
+        let field = multipart
+            .next_field()
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error>)?;
+        let field = match field {
+            Some(field) => field,
+            None => break,
+        };
+        let name = field.name().unwrap_or_default().to_string();
+        let value = field
+            .text()
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error>)?;
+        match name.as_str() {
+            "kind" => kind = Kind::from_param(&value),
+            "file" => content = value,
+            _ => {}
+        }
+    }
+
+    let mut c = tera::Context::new();
+    c.insert("kind", &kind);
+    match import::read(&content) {
+        Ok(upload) if !upload.headers.is_empty() => {
+            let mapping = import::guess_mapping(kind.fields(), &upload.headers);
+            render_mapping(&tera, c, kind, content, &upload, &mapping, Vec::new())
+        }
+        Ok(_) => {
+            c.insert("error", "The file is empty");
+            Ok(Html::from(tera.render("import/upload.html", &c).unwrap()))
+        }
+        Err(e) => {
+            c.insert(
+                "error",
+                &format!("The file could not be read as CSV: {}", e),
+            );
+            Ok(Html::from(tera.render("import/upload.html", &c).unwrap()))
+        }
+    }
+}
+
+fn render_mapping(
+    tera: &Tera,
+    mut c: tera::Context,
+    kind: Kind,
+    content: String,
+    upload: &Upload,
+    mapping: &Mapping,
+    missing: Vec<&str>,
+) -> Result<Html<String>, AppError> {
+    c.insert("kind", &kind);
+    c.insert("fields", kind.fields());
+    c.insert("headers", &upload.headers);
+    c.insert("mapping", mapping);
+    c.insert(
+        "preview",
+        &upload.rows.iter().take(PREVIEW_ROWS).collect::<Vec<_>>(),
+    );
+    c.insert("total_rows", &upload.rows.len());
+    c.insert("missing", &missing);
+    c.insert("content", &content);
+    let r = tera.render("import/mapping.html", &c).unwrap();
+
+    Ok(Html::from(r))
+}
+
+/// `map_<field>` parameters hold the CSV column index of each field, empty when not imported
+fn mapping_of(form: &HashMap<String, String>) -> Mapping {
+    form.iter()
+        .filter_map(|(key, value)| {
+            let field = key.strip_prefix("map_")?;
+            Some((field.to_string(), value.parse().ok()?))
+        })
+        .collect()
+}
+
+/// Validates the file with the chosen mapping. Depending on `action` it shows the
+/// report (`dry_run`), downloads the errors (`errors`) or saves everything (`commit`).
+pub async fn run(
+    Extension(tera): Extension<Tera>,
+    Extension(state): Extension<Arc<Context>>,
+    Extension(clinic): Extension<ActiveClinic>,
+    user: User,
+    Form(form): Form<HashMap<String, String>>,
+) -> Result<Response, AppError> {
+    let kind = Kind::from_param(form.get("kind").map(|k| k.as_str()).unwrap_or_default());
+    let action = form.get("action").map(|a| a.as_str()).unwrap_or("dry_run");
+    let content = form.get("content").cloned().unwrap_or_default();
+    let mapping = mapping_of(&form);
+
+    let upload = import::read(&content).map_err(|e| Box::new(e) as Box<dyn Error>)?;
+    let mut c = tera::Context::new();
+
+    let missing = import::missing_fields(kind.fields(), &mapping);
+    if !missing.is_empty() {
+        return Ok(
+            render_mapping(&tera, c, kind, content, &upload, &mapping, missing)?.into_response(),
+        );
+    }
+
+    let (valid, errors) = match kind {
+        Kind::Pets => {
+            let validated =
+                import::validate_pets(&state.rb, clinic.id, user.id, &upload, &mapping).await?;
+            if action == "commit" && validated.errors.is_empty() {
+                import::commit(&state.rb, &validated.records).await?;
+            }
+            (validated.records.len(), validated.errors)
+        }
+        Kind::Vets => {
+            let validated = import::validate_vets(&state.rb, clinic.id, &upload, &mapping).await?;
+            if action == "commit" && validated.errors.is_empty() {
+                import::commit(&state.rb, &validated.records).await?;
+            }
+            (validated.records.len(), validated.errors)
+        }
+    };
+
+    if action == "errors" {
+        let body = import::error_report(&errors).map_err(|e| Box::new(e) as Box<dyn Error>)?;
+        let headers = [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+            (
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"import-errors.csv\"",
+            ),
+        ];
+        return Ok((headers, body).into_response());
+    }
+
+    c.insert("kind", &kind);
+    c.insert("valid", &valid);
+    c.insert("errors", &errors);
+    c.insert("imported", &(action == "commit" && errors.is_empty()));
+    c.insert("content", &content);
+    c.insert("mapping", &mapping);
+    let r = tera.render("import/report.html", &c).unwrap();
+
+    Ok(Html::from(r).into_response())
+}
@@ -1,4 +1,32 @@
+pub mod api;
 pub mod auth;
+pub mod backups;
+pub mod clinics;
+pub mod conditional;
+pub mod dashboard;
+pub mod events;
+pub mod exports;
+pub mod graphql;
 pub mod home;
+pub mod imports;
+pub mod negotiate;
 pub mod pets;
+pub mod rate_limit;
+pub mod reports;
+pub mod search;
+pub mod searches;
+pub mod tokens;
 pub mod vets;
+pub mod webhooks;
+
+use std::collections::HashMap;
+
+/// Query parameters of a list view minus the paging ones, so pagination and
+/// sorting links keep the current filters
+pub fn list_filters(params: &HashMap<String, String>) -> HashMap<&str, &str> {
+    params
+        .iter()
+        .filter(|(k, _)| !matches!(k.as_str(), "page" | "sort" | "direction"))
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect()
+}
@@ -0,0 +1,97 @@
+//! Lets the HTML pages answer `Accept: application/json` with the data of their template.
+
+use axum::{
+    async_trait,
+    extract::{FromRequest, Json, RequestParts},
+    http::{header, HeaderMap, HeaderValue, Request, StatusCode},
+    middleware::Next,
+    response::{Html, IntoResponse, Response},
+};
+use tera::Tera;
+
+use crate::handlers::api::ApiError;
+
+use std::convert::Infallible;
+
+/// Representation asked for by the client, HTML unless it prefers JSON
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Accept {
+    Html,
+    Json,
+}
+
+impl Accept {
+    /// Picks the most preferred of `text/html` and `application/json` in the `Accept` header
+    pub fn of(headers: &HeaderMap) -> Accept {
+        let accept = headers
+            .get(header::ACCEPT)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default();
+
+        let mut best = (Accept::Html, 0.0);
+        for range in accept.split(',') {
+            let mut parts = range.split(';').map(str::trim);
+            let representation = match parts.next() {
+                Some("application/json") => Accept::Json,
+                Some("text/html") => Accept::Html,
+                _ => continue,
+            };
+            let quality = parts
+                .find_map(|p| p.strip_prefix("q="))
+                .and_then(|q| q.parse().ok())
+                .unwrap_or(1.0);
+            // on a tie the first one listed wins
+            if quality > best.1 {
+                best = (representation, quality);
+            }
+        }
+        best.0
+    }
+}
+
+#[async_trait]
+impl<B> FromRequest<B> for Accept
+where
+    B: Send,
+{
+    type Rejection = Infallible;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        Ok(Accept::of(req.headers()))
+    }
+}
+
+/// Renders `template`, or sends its context as a JSON object
+pub fn render(tera: &Tera, accept: Accept, template: &str, c: tera::Context) -> Response {
+    let mut response = match accept {
+        Accept::Json => Json(c.into_json()).into_response(),
+        Accept::Html => Html::from(tera.render(template, &c).unwrap()).into_response(),
+    };
+    response
+        .headers_mut()
+        .insert(header::VARY, HeaderValue::from_static("accept"));
+
+    response
+}
+
+/// Answer to a JSON request for a record that does not exist
+pub fn not_found(what: &str) -> Response {
+    ApiError::not_found(what).into_response()
+}
+
+/// Message of an `AppError`, left on its response for `errors`
+#[derive(Clone)]
+pub struct ErrorMessage(pub String);
+
+/// Turns the `AppError` pages into the JSON error body of the API for JSON clients
+pub async fn errors<B>(req: Request<B>, next: Next<B>) -> Response {
+    let accept = Accept::of(req.headers());
+    let response = next.run(req).await;
+
+    match (accept, response.extensions().get::<ErrorMessage>()) {
+        (Accept::Json, Some(ErrorMessage(message))) => {
+            ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, message).into_response()
+        }
+        _ => response,
+    }
+}
@@ -1,19 +1,33 @@
 use crate::{
+    handlers::{
+        conditional::Version,
+        exports, list_filters,
+        negotiate::{self, Accept},
+    },
     logic::{
-        pets::{self, Pet},
+        clinics::{ActiveClinic, Scope},
+        events::{self, Action, Change},
+        export::{self, Format},
+        ownership,
+        paging::Paging,
+        pets::{self, BulkAction, Pet, PetFilter},
+        saved_searches,
         users::User,
         vets::{self, Vet},
+        webhooks,
     },
     AppError, Context,
 };
 use axum::{
-    extract::{Extension, Path, Query},
+    extract::{Extension, Path, RawQuery},
+    http::HeaderMap,
     response::{Html, IntoResponse, Redirect, Response},
 };
 
 use axum_extra::extract::Form;
 use chrono::Utc;
-use serde::Deserialize;
+use rbatis::plugin::page::Page;
+use serde::{Deserialize, Serialize};
 use tera::Tera;
 
 use std::{collections::HashMap, sync::Arc};
@@ -22,11 +36,48 @@ use std::{collections::HashMap, sync::Arc};
 pub struct PetForm {
     id: u32,
     name: String,
+    /// only used for new pets, existing ones change owner through a transfer
+    #[serde(default)]
     owner_name: String,
+    #[serde(default)]
     owner_phone: String,
     age: u32,
     current_vet: u32,
     pet_type: u32,
+    /// set once the user has reviewed the possible duplicates and still wants to save
+    #[serde(default)]
+    confirmed: bool,
+}
+
+#[derive(Deserialize, Serialize, Default)]
+pub struct TransferForm {
+    new_owner_name: String,
+    new_owner_phone: String,
+    reason: String,
+}
+
+#[derive(Deserialize)]
+pub struct MergeForm {
+    duplicate: u32,
+}
+
+/// Action on the pets checked in the list
+#[derive(Deserialize)]
+pub struct BulkForm {
+    action: String,
+    #[serde(default)]
+    ids: Vec<u32>,
+    /// 0 removes the vet
+    #[serde(default)]
+    vet_id: u32,
+    #[serde(default)]
+    pet_type: u32,
+    /// format of the export
+    #[serde(default)]
+    format: String,
+    /// query string of the list, to go back to it
+    #[serde(default)]
+    query: String,
 }
 
 impl From<Form<PetForm>> for Pet {
@@ -44,6 +95,9 @@ impl From<Form<PetForm>> for Pet {
             pet_type: form.pet_type,
             created_by: 0,
             created_at: Utc::now().naive_utc(),
+            clinic_id: 0,
+            archived: false,
+            updated_at: None,
         }
     }
 }
@@ -51,65 +105,145 @@ impl From<Form<PetForm>> for Pet {
 pub async fn save(
     pet_form: axum_extra::extract::Form<PetForm>,
     user: User,
+    scope: Scope,
+    Extension(clinic): Extension<ActiveClinic>,
+    Extension(tera): Extension<Tera>,
     Extension(state): Extension<Arc<Context>>,
-) -> Result<impl IntoResponse, AppError> {
+) -> Result<Response, AppError> {
     // let mut txn = state.pets.get_pool().begin().await.unwrap();
 
-    let c = pets::get(&state.rb, pet_form.id).await?;
+    let c = pets::get(&state.rb, &scope, pet_form.id).await?;
 
     if pet_form.id == 0 {
+        let confirmed = pet_form.confirmed;
         let mut pet: Pet = pet_form.into();
         pet.created_by = user.id;
-        pets::save(&state.rb, &pet).await?;
+        pet.clinic_id = clinic.id;
+        pet.vet_id = vet_of_clinic(&state, pet.clinic_id, pet.vet_id).await?;
+
+        if !confirmed {
+            let duplicates = pets::find_duplicates(&state.rb, &pet).await?;
+            if !duplicates.is_empty() {
+                let mut c = tera::Context::new();
+                c.insert("pet", &pet);
+                c.insert("duplicates", &duplicates);
+                c.insert("pet_types", &pets::types());
+                let r = tera.render("pet/duplicates.html", &c).unwrap();
+
+                return Ok(Html::from(r).into_response());
+            }
+        }
+        pet.id = pets::save(&state.rb, &pet).await?;
+        webhooks::publish(&state.rb, pet.clinic_id, webhooks::PET_CREATED, &pet).await;
+        events::publish(&state, Change::pet(Action::Created, &pet));
     } else {
         if c.is_none() {
-            return Ok(Redirect::to("/pets"));
+            return Ok(Redirect::to("/pets").into_response());
         }
         let mut c = c.unwrap();
 
         c.name = pet_form.name.clone();
-        c.owner_name = pet_form.owner_name.clone();
-        c.owner_phone = pet_form.owner_phone.clone();
         c.age = pet_form.age;
         c.pet_type = pet_form.pet_type;
 
         if pet_form.current_vet > 0 {
-            c.vet_id = Some(pet_form.current_vet);
+            c.vet_id = vet_of_clinic(&state, c.clinic_id, Some(pet_form.current_vet)).await?;
         } else {
             c.vet_id = None
         }
         pets::save(&state.rb, &c).await?;
+        webhooks::publish(&state.rb, c.clinic_id, webhooks::PET_UPDATED, &c).await;
+        events::publish(&state, Change::pet(Action::Updated, &c));
     }
 
-    Ok(Redirect::to("/pets"))
+    Ok(Redirect::to("/pets").into_response())
+}
+
+/// Only keeps vets belonging to the pet's clinic
+async fn vet_of_clinic(
+    state: &Context,
+    clinic_id: u32,
+    vet_id: Option<u32>,
+) -> Result<Option<u32>, AppError> {
+    match vet_id {
+        Some(id) => Ok(vets::get(&state.rb, &Scope::Clinic(clinic_id), id)
+            .await?
+            .map(|v| v.id)),
+        None => Ok(None),
+    }
+}
+
+/// The pet list filtered by `query`, which is encoded again so the URL is always a valid
+/// `Location`. Never the bare `/pets`, which would open the default search.
+pub fn list_url(query: &str) -> String {
+    format!(
+        "/pets?{}",
+        saved_searches::encode_query(query).unwrap_or_default()
+    )
 }
 
 pub async fn list(
     Extension(tera): Extension<Tera>,
     Extension(state): Extension<Arc<Context>>,
-    Query(params): Query<HashMap<String, String>>,
-) -> Result<Html<String>, AppError> {
+    Extension(clinic): Extension<ActiveClinic>,
+    user: User,
+    scope: Scope,
+    accept: Accept,
+    RawQuery(query): RawQuery,
+) -> Result<Response, AppError> {
     let mut c = tera::Context::new();
+    let params: HashMap<String, String> =
+        serde_urlencoded::from_str(query.as_deref().unwrap_or_default()).unwrap_or_default();
 
-    let name = params.get("name");
-    let pets = pets::search(&state.rb, name).await?;
+    // Opening the plain list shows the user's default saved search, if any
+    if query.is_none() {
+        if let Some(default) = saved_searches::default_of(&state.rb, &user).await? {
+            return Ok(Redirect::to(&list_url(&default.query)).into_response());
+        }
+    }
+
+    let filter = PetFilter::from_query(&params);
+    let paging = Paging::from_query(&params, pets::SORTABLE);
+    let page = match filter.query_error {
+        // don't list everything when the query could not be understood
+        Some(_) => Page::new(paging.page, paging.page_size),
+        None => pets::search(&state.rb, &scope, &filter, &paging).await?,
+    };
+    let vets = match scope {
+        Scope::Clinic(_) => vets::active(&state.rb, &scope).await?,
+        Scope::All => Vec::new(),
+    };
 
     let types = pets::types();
-    c.insert("pets", &pets);
+    c.insert("pets", &page.records);
+    c.insert("filter", &filter);
+    c.insert("vets", &vets);
+    c.insert("page", &page);
+    c.insert("paging", &paging);
+    c.insert("filters", &list_filters(&params));
     c.insert("pet_types", &types);
-    let r = tera.render("pet/list.html", &c).unwrap();
+    c.insert("all_clinics", &scope.is_all());
+    c.insert("is_admin", &clinic.is_admin);
+    c.insert("export_columns", export::PET_COLUMNS);
+    c.insert(
+        "query",
+        &saved_searches::clean_query(&query.unwrap_or_default()).unwrap_or_default(),
+    );
 
-    Ok(Html::from(r))
+    Ok(negotiate::render(&tera, accept, "pet/list.html", c))
 }
 
 pub async fn delete(
     Extension(state): Extension<Arc<Context>>,
     _user: User,
+    scope: Scope,
     Path(id): Path<u32>,
 ) -> Result<impl IntoResponse, AppError> {
-    let pet = pets::get(&state.rb, id).await?;
+    let pet = pets::get(&state.rb, &scope, id).await?;
     if let Some(pet) = pet {
         pets::delete(&state.rb, &pet).await?;
+        webhooks::publish(&state.rb, pet.clinic_id, webhooks::PET_DELETED, &pet).await;
+        events::publish(&state, Change::pet(Action::Deleted, &pet));
     }
     Ok(Redirect::to("/pets"))
 }
@@ -117,32 +251,228 @@ pub async fn delete(
 pub async fn get(
     Extension(tera): Extension<Tera>,
     Extension(state): Extension<Arc<Context>>,
+    Extension(clinic): Extension<ActiveClinic>,
     _user: User,
+    scope: Scope,
+    headers: HeaderMap,
     Path(id): Path<u32>,
 ) -> Result<Response, AppError> {
     let mut c = tera::Context::new();
+    let accept = Accept::of(&headers);
 
-    let mut pet = pets::get(&state.rb, id).await?;
+    let mut pet = pets::get(&state.rb, &scope, id).await?;
 
     if id == 0 {
-        pet = Some(pets::Pet::default());
+        pet = Some(pets::Pet {
+            clinic_id: clinic.id,
+            ..Default::default()
+        });
     }
     if pet.is_none() {
-        return Ok(Redirect::to("/pets").into_response());
+        return Ok(match accept {
+            Accept::Json => negotiate::not_found("pet"),
+            Accept::Html => Redirect::to("/pets").into_response(),
+        });
     }
     let pet = pet.unwrap();
 
-    let vets: Vec<Vet> = vets::search(&state.rb, None).await?;
+    let vets: Vec<Vet> = vets::active(&state.rb, &Scope::Clinic(pet.clinic_id)).await?;
 
     //    let current_vet: Option<Vet> = vets::of_pet(&state.rb, &pet).await;
 
     let types = pets::types();
+    let history = ownership::history(&state.rb, &pet).await?;
 
     c.insert("pet_types", &types);
     //  c.insert("current_vet", &current_vet);
+    c.insert("ownership_history", &history);
     c.insert("pet", &pet);
+    c.insert("all_clinics", &scope.is_all());
     c.insert("vets", &vets);
-    let r = tera.render("pet/edit.html", &c).unwrap();
+
+    // the form of a new pet has no version
+    if id == 0 {
+        return Ok(negotiate::render(&tera, accept, "pet/edit.html", c));
+    }
+    let version = Version::of(&c.clone().into_json(), accept, Some(pet.last_modified()));
+    let response = negotiate::render(&tera, accept, "pet/edit.html", c);
+
+    Ok(version.respond(&headers, response))
+}
+
+pub async fn merge(
+    Extension(tera): Extension<Tera>,
+    Extension(state): Extension<Arc<Context>>,
+    _user: User,
+    scope: Scope,
+    Path(id): Path<u32>,
+) -> Result<Response, AppError> {
+    let mut c = tera::Context::new();
+
+    let pet = match pets::get(&state.rb, &scope, id).await? {
+        Some(pet) => pet,
+        None => return Ok(Redirect::to("/pets").into_response()),
+    };
+    let duplicates = pets::find_duplicates(&state.rb, &pet).await?;
+
+    c.insert("pet", &pet);
+    c.insert("duplicates", &duplicates);
+    c.insert("pet_types", &pets::types());
+    let r = tera.render("pet/merge.html", &c).unwrap();
+
+    Ok(Html::from(r).into_response())
+}
+
+pub async fn post_merge(
+    Extension(state): Extension<Arc<Context>>,
+    _user: User,
+    scope: Scope,
+    Path(id): Path<u32>,
+    form: Form<MergeForm>,
+) -> Result<impl IntoResponse, AppError> {
+    if form.duplicate == id {
+        return Ok(Redirect::to(&format!("/pets/merge/{}", id)));
+    }
+
+    let keep = pets::get(&state.rb, &scope, id).await?;
+    let duplicate = pets::get(&state.rb, &scope, form.duplicate).await?;
+
+    match (keep, duplicate) {
+        // records of different clinics are never the same animal, see `find_duplicates`
+        (Some(keep), Some(duplicate)) if keep.clinic_id != duplicate.clinic_id => {
+            Ok(Redirect::to(&format!("/pets/merge/{}", keep.id)))
+        }
+        (Some(keep), Some(duplicate)) => {
+            pets::merge(&state.rb, &keep, &duplicate).await?;
+            let clinic_id = duplicate.clinic_id;
+            webhooks::publish(&state.rb, clinic_id, webhooks::PET_DELETED, &duplicate).await;
+            events::publish(&state, Change::pet(Action::Deleted, &duplicate));
+            if let Some(merged) = pets::get(&state.rb, &scope, keep.id).await? {
+                let clinic_id = merged.clinic_id;
+                webhooks::publish(&state.rb, clinic_id, webhooks::PET_UPDATED, &merged).await;
+                events::publish(&state, Change::pet(Action::Updated, &merged));
+            }
+            Ok(Redirect::to(&format!("/pets/{}", keep.id)))
+        }
+        _ => Ok(Redirect::to("/pets")),
+    }
+}
+
+pub async fn transfer(
+    Extension(tera): Extension<Tera>,
+    Extension(state): Extension<Arc<Context>>,
+    _user: User,
+    scope: Scope,
+    Path(id): Path<u32>,
+) -> Result<Response, AppError> {
+    let mut c = tera::Context::new();
+
+    let pet = match pets::get(&state.rb, &scope, id).await? {
+        Some(pet) => pet,
+        None => return Ok(Redirect::to("/pets").into_response()),
+    };
+
+    c.insert("pet", &pet);
+    c.insert("all_clinics", &scope.is_all());
+    c.insert("transfer", &TransferForm::default());
+    let r = tera.render("pet/transfer.html", &c).unwrap();
+
+    Ok(Html::from(r).into_response())
+}
+
+pub async fn post_transfer(
+    Extension(tera): Extension<Tera>,
+    Extension(state): Extension<Arc<Context>>,
+    user: User,
+    scope: Scope,
+    Path(id): Path<u32>,
+    form: Form<TransferForm>,
+) -> Result<Response, AppError> {
+    let pet = match pets::get(&state.rb, &scope, id).await? {
+        Some(pet) => pet,
+        None => return Ok(Redirect::to("/pets").into_response()),
+    };
+
+    if let Some(error) = ownership::check(&form.new_owner_name, &form.new_owner_phone, &form.reason)
+    {
+        let mut c = tera::Context::new();
+        c.insert("pet", &pet);
+        c.insert("all_clinics", &scope.is_all());
+        c.insert("transfer", &form.0);
+        c.insert("error", error);
+        let r = tera.render("pet/transfer.html", &c).unwrap();
+
+        return Ok(Html::from(r).into_response());
+    }
+
+    ownership::transfer(
+        &state.rb,
+        &pet,
+        form.new_owner_name.trim(),
+        form.new_owner_phone.trim(),
+        form.reason.trim(),
+        user.id,
+    )
+    .await?;
+    if let Some(updated) = pets::get(&state.rb, &scope, pet.id).await? {
+        webhooks::publish(&state.rb, pet.clinic_id, webhooks::PET_UPDATED, &updated).await;
+        events::publish(&state, Change::pet(Action::Updated, &updated));
+    }
+
+    Ok(Redirect::to(&format!("/pets/{}", pet.id)).into_response())
+}
+
+pub async fn bulk(
+    Extension(tera): Extension<Tera>,
+    Extension(state): Extension<Arc<Context>>,
+    _user: User,
+    scope: Scope,
+    Form(form): Form<BulkForm>,
+) -> Result<Response, AppError> {
+    let back = list_url(&form.query);
+    if form.ids.is_empty() {
+        return Ok(Redirect::to(&back).into_response());
+    }
+
+    let (action, label) = match form.action.as_str() {
+        "export" => {
+            let format = Format::from_param(&form.format);
+            return exports::selected_pets(state.clone(), scope, form.ids, format).await;
+        }
+        "vet" => match form.vet_id {
+            0 => (BulkAction::AssignVet(None), "Vet removed".to_string()),
+            vet_id => {
+                let name = vets::get(&state.rb, &scope, vet_id)
+                    .await?
+                    .map(|v| v.name)
+                    .unwrap_or_default();
+                (
+                    BulkAction::AssignVet(Some(vet_id)),
+                    format!("Reassigned to {}", name),
+                )
+            }
+        },
+        "type" => (
+            BulkAction::ChangeType(form.pet_type),
+            format!("Type changed to {}", pets::type_label(form.pet_type)),
+        ),
+        "archive" => (BulkAction::Archive, "Archived".to_string()),
+        "restore" => (BulkAction::Restore, "Restored".to_string()),
+        _ => return Ok(Redirect::to(&back).into_response()),
+    };
+
+    let result = pets::bulk_update(&state.rb, &scope, &form.ids, action).await?;
+    for pet in &result.updated {
+        webhooks::publish(&state.rb, pet.clinic_id, webhooks::PET_UPDATED, pet).await;
+        events::publish(&state, Change::pet(Action::Updated, pet));
+    }
+
+    let mut c = tera::Context::new();
+    c.insert("label", &label);
+    c.insert("result", &result);
+    c.insert("pet_types", &pets::types());
+    c.insert("back", &back);
+    let r = tera.render("pet/bulk.html", &c).unwrap();
 
     Ok(Html::from(r).into_response())
 }
@@ -0,0 +1,106 @@
+//! Rate limits of the login form and of the API, see `logic::rate_limit`. Clients over the
+//! limit get a 429 with `Retry-After`.
+
+use axum::{
+    body::Body,
+    extract::ConnectInfo,
+    http::{header, HeaderValue, Request, StatusCode},
+    middleware::Next,
+    response::{Html, IntoResponse, Response},
+};
+use serde::Deserialize;
+
+use crate::{
+    handlers::api::{ApiError, ApiUser},
+    logic::rate_limit::{self, Group},
+    Context,
+};
+
+use std::{net::SocketAddr, sync::Arc};
+
+#[derive(Deserialize)]
+struct LoginFields {
+    #[serde(default)]
+    username: String,
+}
+
+/// Counts the login attempts by address and by the username of the form
+pub async fn login(req: Request<Body>, next: Next<Body>) -> Response {
+    let (parts, body) = req.into_parts();
+    let body = match hyper::body::to_bytes(body).await {
+        Ok(body) => body,
+        Err(_) => return StatusCode::BAD_REQUEST.into_response(),
+    };
+    let username = serde_urlencoded::from_bytes::<LoginFields>(&body)
+        .ok()
+        .map(|fields| fields.username.trim().to_lowercase());
+
+    limit(
+        Group::Login,
+        Request::from_parts(parts, Body::from(body)),
+        next,
+        username,
+    )
+    .await
+}
+
+/// Counts the API calls by address and by user, once the caller is authenticated
+pub async fn api(req: Request<Body>, next: Next<Body>) -> Response {
+    let username = req
+        .extensions()
+        .get::<ApiUser>()
+        .map(|ApiUser(user)| user.username.to_lowercase());
+
+    limit(Group::Api, req, next, username).await
+}
+
+async fn limit(
+    group: Group,
+    req: Request<Body>,
+    next: Next<Body>,
+    username: Option<String>,
+) -> Response {
+    let state = match req.extensions().get::<Arc<Context>>() {
+        Some(state) => state.clone(),
+        None => return next.run(req).await,
+    };
+
+    let mut clients = Vec::new();
+    if let Some(ConnectInfo(address)) = req.extensions().get::<ConnectInfo<SocketAddr>>() {
+        clients.push(format!("ip:{}", address.ip()));
+    }
+    if let Some(username) = username.filter(|u| !u.is_empty()) {
+        clients.push(format!("user:{}", username));
+    }
+    // every key is counted, the longest wait wins
+    let wait = clients
+        .iter()
+        .filter_map(|client| rate_limit::hit(&state, group, client))
+        .max();
+
+    match wait {
+        Some(seconds) => too_many_requests(group, seconds),
+        None => next.run(req).await,
+    }
+}
+
+fn too_many_requests(group: Group, seconds: u64) -> Response {
+    let mut response = match group {
+        Group::Api => {
+            ApiError::new(StatusCode::TOO_MANY_REQUESTS, "too many requests").into_response()
+        }
+        Group::Login => (
+            StatusCode::TOO_MANY_REQUESTS,
+            Html::from(format!(
+                "Too many login attempts, try again in {} seconds",
+                seconds
+            )),
+        )
+            .into_response(),
+    };
+    response
+        .headers_mut()
+        .insert(header::RETRY_AFTER, HeaderValue::from(seconds));
+
+    response
+}
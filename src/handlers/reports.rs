@@ -0,0 +1,92 @@
+use crate::{
+    logic::{
+        clinics::Scope,
+        export::{self, Format},
+        stats,
+        users::User,
+    },
+    AppError, Context,
+};
+use axum::{
+    extract::{Extension, Query},
+    http::header,
+    response::{Html, IntoResponse, Response},
+};
+
+use chrono::{naive::NaiveDate, Duration, Utc};
+use serde::Deserialize;
+use tera::Tera;
+
+use std::{error::Error, sync::Arc};
+
+/// Days covered by the report when no range is given
+const DEFAULT_PERIOD_DAYS: i64 = 30;
+
+#[derive(Deserialize)]
+pub struct WorkloadParams {
+    #[serde(default)]
+    from: String,
+    #[serde(default)]
+    to: String,
+    /// csv or xlsx to download the report instead of showing it
+    format: Option<String>,
+}
+
+fn date(value: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d").ok()
+}
+
+pub async fn workload(
+    Extension(tera): Extension<Tera>,
+    Extension(state): Extension<Arc<Context>>,
+    _user: User,
+    scope: Scope,
+    Query(params): Query<WorkloadParams>,
+) -> Result<Response, AppError> {
+    let to = date(&params.to).unwrap_or_else(|| Utc::now().naive_utc().date());
+    let from = date(&params.from).unwrap_or(to - Duration::days(DEFAULT_PERIOD_DAYS - 1));
+    let (from, to) = if from > to { (to, from) } else { (from, to) };
+
+    let rows = stats::workload(&state.rb, &scope, from, to).await?;
+
+    if let Some(format) = &params.format {
+        let format = Format::from_param(format);
+        let columns: Vec<&export::Column> = export::WORKLOAD_COLUMNS.iter().collect();
+        let values: Vec<Vec<String>> = rows
+            .iter()
+            .map(|w| export::workload_row(w, &columns))
+            .collect();
+        let body = match format {
+            Format::Csv => {
+                export::csv(&columns, &values, true).map_err(|e| Box::new(e) as Box<dyn Error>)?
+            }
+            Format::Xlsx => export::xlsx("workload", &columns, &values)
+                .map_err(|e| Box::new(e) as Box<dyn Error>)?,
+        };
+        let headers = [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!(
+                    "attachment; filename=\"workload-{}-{}.{}\"",
+                    from,
+                    to,
+                    format.extension()
+                ),
+            ),
+        ];
+        return Ok((headers, body).into_response());
+    }
+
+    let mut c = tera::Context::new();
+    c.insert("rows", &rows);
+    c.insert("from", &from.to_string());
+    c.insert("to", &to.to_string());
+    c.insert("days", &((to - from).num_days() + 1));
+    c.insert("total_pets", &rows.iter().map(|w| w.pets).sum::<u64>());
+    c.insert("total_visits", &rows.iter().map(|w| w.visits).sum::<u64>());
+    c.insert("all_clinics", &scope.is_all());
+    let r = tera.render("reports/workload.html", &c).unwrap();
+
+    Ok(Html::from(r).into_response())
+}
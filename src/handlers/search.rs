@@ -0,0 +1,37 @@
+use crate::{
+    logic::{clinics::Scope, search},
+    AppError, Context,
+};
+use axum::{
+    extract::{Extension, Query},
+    response::Html,
+};
+use serde::Deserialize;
+use tera::Tera;
+
+use std::sync::Arc;
+
+#[derive(Deserialize)]
+pub struct SearchParams {
+    #[serde(default)]
+    q: String,
+}
+
+pub async fn search(
+    Extension(tera): Extension<Tera>,
+    Extension(state): Extension<Arc<Context>>,
+    scope: Scope,
+    Query(params): Query<SearchParams>,
+) -> Result<Html<String>, AppError> {
+    let mut c = tera::Context::new();
+
+    let results = search::search(&state.rb, &scope, &params.q).await?;
+
+    c.insert("q", &params.q);
+    c.insert("groups", &results.groups());
+    c.insert("empty", &results.is_empty());
+    c.insert("all_clinics", &scope.is_all());
+    let r = tera.render("search/results.html", &c).unwrap();
+
+    Ok(Html::from(r))
+}
@@ -0,0 +1,77 @@
+use crate::{
+    handlers::pets,
+    logic::{saved_searches, users::User},
+    AppError, Context,
+};
+use axum::{
+    extract::{Extension, Path},
+    response::{Html, IntoResponse, Redirect},
+};
+use axum_extra::extract::Form;
+use serde::Deserialize;
+use tera::Tera;
+
+use std::sync::Arc;
+
+#[derive(Deserialize)]
+pub struct SaveSearchForm {
+    name: String,
+    /// query string of the pet list being saved
+    #[serde(default)]
+    query: String,
+    #[serde(default)]
+    is_default: bool,
+}
+
+pub async fn list(
+    Extension(tera): Extension<Tera>,
+    Extension(state): Extension<Arc<Context>>,
+    user: User,
+) -> Result<Html<String>, AppError> {
+    let mut c = tera::Context::new();
+
+    let searches = saved_searches::of_user(&state.rb, &user).await?;
+
+    c.insert("searches", &searches);
+    let r = tera.render("search/saved.html", &c).unwrap();
+
+    Ok(Html::from(r))
+}
+
+pub async fn save(
+    Extension(state): Extension<Arc<Context>>,
+    user: User,
+    form: Form<SaveSearchForm>,
+) -> Result<impl IntoResponse, AppError> {
+    let name = form.name.trim();
+    let query = match saved_searches::clean_query(&form.query) {
+        Some(query) if !name.is_empty() => query,
+        _ => return Ok(Redirect::to(&pets::list_url(&form.query))),
+    };
+
+    saved_searches::save(&state.rb, &user, name, &query, form.is_default).await?;
+
+    Ok(Redirect::to("/searches"))
+}
+
+pub async fn toggle_default(
+    Extension(state): Extension<Arc<Context>>,
+    user: User,
+    Path(id): Path<u32>,
+) -> Result<impl IntoResponse, AppError> {
+    if let Some(search) = saved_searches::get(&state.rb, &user, id).await? {
+        saved_searches::toggle_default(&state.rb, &user, &search).await?;
+    }
+    Ok(Redirect::to("/searches"))
+}
+
+pub async fn delete(
+    Extension(state): Extension<Arc<Context>>,
+    user: User,
+    Path(id): Path<u32>,
+) -> Result<impl IntoResponse, AppError> {
+    if let Some(search) = saved_searches::get(&state.rb, &user, id).await? {
+        saved_searches::delete(&state.rb, &search).await?;
+    }
+    Ok(Redirect::to("/searches"))
+}
@@ -0,0 +1,82 @@
+use crate::{
+    logic::{
+        tokens::{self, TokenScope},
+        users::User,
+    },
+    AppError, Context,
+};
+use axum::{
+    extract::{Extension, Path},
+    response::{Html, IntoResponse, Redirect},
+};
+use axum_extra::extract::Form;
+use serde::Deserialize;
+use tera::Tera;
+
+use std::sync::Arc;
+
+#[derive(Deserialize)]
+pub struct TokenForm {
+    name: String,
+    scope: String,
+    days: i64,
+}
+
+async fn render(
+    tera: &Tera,
+    state: &Context,
+    user: &User,
+    created: Option<String>,
+    error: Option<&str>,
+) -> Result<Html<String>, AppError> {
+    let mut c = tera::Context::new();
+
+    let token_list = tokens::of_user(&state.rb, user).await?;
+    let expired: Vec<bool> = token_list.iter().map(|t| t.is_expired()).collect();
+
+    c.insert("tokens", &token_list);
+    c.insert("expired", &expired);
+    c.insert("expiry_days", tokens::EXPIRY_DAYS);
+    c.insert("created", &created);
+    c.insert("error", &error);
+    let r = tera.render("token/list.html", &c).unwrap();
+
+    Ok(Html::from(r))
+}
+
+pub async fn list(
+    Extension(tera): Extension<Tera>,
+    Extension(state): Extension<Arc<Context>>,
+    user: User,
+) -> Result<Html<String>, AppError> {
+    render(&tera, &state, &user, None, None).await
+}
+
+/// Creates the token and shows it, once
+pub async fn create(
+    Extension(tera): Extension<Tera>,
+    Extension(state): Extension<Arc<Context>>,
+    user: User,
+    form: Form<TokenForm>,
+) -> Result<Html<String>, AppError> {
+    let name = form.name.trim();
+    let scope = TokenScope::from_param(&form.scope);
+    if name.is_empty() || scope.is_none() || !tokens::EXPIRY_DAYS.contains(&form.days) {
+        let error = "Give the token a name, a scope and a lifetime.";
+        return render(&tera, &state, &user, None, Some(error)).await;
+    }
+
+    let secret = tokens::create(&state.rb, &user, name, scope.unwrap(), form.days).await?;
+
+    render(&tera, &state, &user, Some(secret), None).await
+}
+
+pub async fn revoke(
+    Extension(state): Extension<Arc<Context>>,
+    user: User,
+    Path(id): Path<u32>,
+) -> Result<impl IntoResponse, AppError> {
+    tokens::revoke(&state.rb, &user, id).await?;
+
+    Ok(Redirect::to("/tokens"))
+}
@@ -1,13 +1,24 @@
 use crate::{
+    handlers::{
+        conditional::Version,
+        list_filters,
+        negotiate::{self, Accept},
+    },
     logic::{
+        clinics::{ActiveClinic, Scope},
+        events::{self, Action, Change},
+        export,
+        paging::Paging,
         users::User,
         vets::{self, Vet},
+        webhooks,
     },
     AppError, Context,
 };
 use axum::{
     extract::{Extension, Path, Query},
-    response::{Html, IntoResponse, Redirect},
+    http::HeaderMap,
+    response::{Html, IntoResponse, Redirect, Response},
 };
 
 use serde::Deserialize;
@@ -20,20 +31,33 @@ pub struct VetForm {
     id: u32,
     name: String,
 }
+
+#[derive(Deserialize)]
+pub struct ReassignForm {
+    /// vet receiving the patients, 0 leaves them unassigned
+    target_vet: u32,
+}
 pub async fn save(
     vet: axum_extra::extract::Form<VetForm>,
+    scope: Scope,
+    Extension(clinic): Extension<ActiveClinic>,
     Extension(state): Extension<Arc<Context>>,
 ) -> Result<impl IntoResponse, AppError> {
-    if let Some(mut v) = vets::get(&state.rb, vet.id).await? {
+    if let Some(mut v) = vets::get(&state.rb, &scope, vet.id).await? {
         v.name = vet.name.clone();
         vets::save(&state.rb, &v).await?;
+        events::publish(&state, Change::vet(Action::Updated, &v));
     } else {
         // Adding a new one
-        let v = Vet {
+        let mut v = Vet {
             id: 0,
             name: vet.name.clone(),
+            active: true,
+            clinic_id: clinic.id,
+            updated_at: None,
         };
-        vets::save(&state.rb, &v).await?;
+        v.id = vets::save(&state.rb, &v).await?;
+        events::publish(&state, Change::vet(Action::Created, &v));
     }
     Ok(Redirect::to("/vets"))
 }
@@ -41,49 +65,146 @@ pub async fn save(
 pub async fn list(
     Extension(tera): Extension<Tera>,
     Extension(state): Extension<Arc<Context>>,
+    Extension(clinic): Extension<ActiveClinic>,
+    scope: Scope,
+    accept: Accept,
     Query(params): Query<HashMap<String, String>>,
-) -> Result<Html<String>, AppError> {
+) -> Result<Response, AppError> {
     let mut c = tera::Context::new();
 
     let name = params.get("name");
-    let vets: Vec<Vet> = vets::search(&state.rb, name).await?;
+    let paging = Paging::from_query(&params, vets::SORTABLE);
+    let page = vets::search(&state.rb, &scope, name, &paging).await?;
 
-    c.insert("vets", &vets);
-    let r = tera.render("vet/list.html", &c).unwrap();
+    c.insert("vets", &page.records);
+    c.insert("page", &page);
+    c.insert("paging", &paging);
+    c.insert("filters", &list_filters(&params));
+    c.insert("all_clinics", &scope.is_all());
+    c.insert("is_admin", &clinic.is_admin);
+    c.insert("export_columns", export::VET_COLUMNS);
 
-    Ok(Html::from(r))
+    Ok(negotiate::render(&tera, accept, "vet/list.html", c))
 }
 pub async fn get(
     Extension(tera): Extension<Tera>,
     Extension(state): Extension<Arc<Context>>,
     _user: User,
+    scope: Scope,
+    headers: HeaderMap,
     Path(id): Path<u32>,
-) -> Result<Html<String>, AppError> {
+) -> Result<Response, AppError> {
     let mut c = tera::Context::new();
+    let accept = Accept::of(&headers);
 
-    let mut vet = vets::get(&state.rb, id).await?;
+    let mut vet = vets::get(&state.rb, &scope, id).await?;
 
     if id == 0 {
         vet = Some(Vet::default());
     }
-    if vet.is_none() {
-        return Ok(Html::from("Vet not found".to_string()));
-    }
+    let vet = match vet {
+        Some(vet) => vet,
+        None => {
+            return Ok(match accept {
+                Accept::Json => negotiate::not_found("vet"),
+                Accept::Html => Html::from("Vet not found".to_string()).into_response(),
+            })
+        }
+    };
 
     c.insert("vet", &vet);
-    let r = tera.render("vet/edit.html", &c).unwrap();
+    c.insert("all_clinics", &scope.is_all());
+
+    // the form of a new vet has no version
+    if id == 0 {
+        return Ok(negotiate::render(&tera, accept, "vet/edit.html", c));
+    }
+    let version = Version::of(&c.clone().into_json(), accept, vet.updated_at);
+    let response = negotiate::render(&tera, accept, "vet/edit.html", c);
 
-    Ok(Html::from(r))
+    Ok(version.respond(&headers, response))
 }
 
 pub async fn delete(
     Extension(state): Extension<Arc<Context>>,
     _user: User,
+    scope: Scope,
     Path(id): Path<u32>,
 ) -> Result<impl IntoResponse, AppError> {
-    let vet = vets::get(&state.rb, id).await?;
+    let vet = vets::get(&state.rb, &scope, id).await?;
     if let Some(vet) = vet {
+        // Vets with patients or visit history can only be deactivated
+        // through the reassignment wizard
+        if !vets::dependents(&state.rb, &vet).await?.is_empty() {
+            return Ok(Redirect::to(&format!("/vets/reassign/{}", vet.id)));
+        }
         vets::delete(&state.rb, &vet).await?;
+        events::publish(&state, Change::vet(Action::Deleted, &vet));
     }
     Ok(Redirect::to("/vets"))
 }
+
+pub async fn reassign(
+    Extension(tera): Extension<Tera>,
+    Extension(state): Extension<Arc<Context>>,
+    _user: User,
+    scope: Scope,
+    Path(id): Path<u32>,
+) -> Result<Response, AppError> {
+    let mut c = tera::Context::new();
+
+    let vet = match vets::get(&state.rb, &scope, id).await? {
+        Some(vet) => vet,
+        None => return Ok(Redirect::to("/vets").into_response()),
+    };
+
+    let dependents = vets::dependents(&state.rb, &vet).await?;
+    let others: Vec<Vet> = vets::active(&state.rb, &Scope::Clinic(vet.clinic_id))
+        .await?
+        .into_iter()
+        .filter(|v| v.id != vet.id)
+        .collect();
+
+    c.insert("vet", &vet);
+    c.insert("dependents", &dependents);
+    c.insert("vets", &others);
+    let r = tera.render("vet/reassign.html", &c).unwrap();
+
+    Ok(Html::from(r).into_response())
+}
+
+pub async fn post_reassign(
+    Extension(state): Extension<Arc<Context>>,
+    _user: User,
+    scope: Scope,
+    Path(id): Path<u32>,
+    form: axum_extra::extract::Form<ReassignForm>,
+) -> Result<impl IntoResponse, AppError> {
+    let vet = match vets::get(&state.rb, &scope, id).await? {
+        Some(vet) => vet,
+        None => return Ok(Redirect::to("/vets")),
+    };
+
+    // Patients can only move to another active vet of the same clinic
+    let clinic = Scope::Clinic(vet.clinic_id);
+    let target = match form.target_vet {
+        0 => None,
+        n if n == vet.id => return Ok(Redirect::to(&format!("/vets/reassign/{}", vet.id))),
+        n => match vets::get(&state.rb, &clinic, n).await? {
+            Some(target) if target.active => Some(target.id),
+            _ => return Ok(Redirect::to(&format!("/vets/reassign/{}", vet.id))),
+        },
+    };
+    let moved = vets::reassign_and_deactivate(&state.rb, &vet, target).await?;
+    for pet in &moved {
+        webhooks::publish(&state.rb, pet.clinic_id, webhooks::PET_UPDATED, pet).await;
+        events::publish(&state, Change::pet(Action::Updated, pet));
+    }
+    let deactivated = Vet {
+        active: false,
+        ..vet
+    };
+    events::publish(&state, Change::vet(Action::Updated, &deactivated));
+
+    Ok(Redirect::to("/vets"))
+}
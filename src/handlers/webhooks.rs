@@ -0,0 +1,195 @@
+use crate::{
+    logic::{
+        clinics::ActiveClinic,
+        users::User,
+        webhooks::{self, Webhook},
+    },
+    AppError, Context,
+};
+use axum::{
+    extract::{Extension, Path},
+    response::{Html, IntoResponse, Redirect, Response},
+};
+use axum_extra::extract::Form;
+use chrono::Utc;
+use serde::Deserialize;
+use serde_json::json;
+use tera::Tera;
+
+use std::sync::Arc;
+
+#[derive(Deserialize)]
+pub struct WebhookForm {
+    url: String,
+    #[serde(default)]
+    events: Vec<String>,
+    /// generated when left empty
+    #[serde(default)]
+    secret: String,
+}
+
+fn is_valid_url(url: &str) -> bool {
+    match reqwest::Url::parse(url) {
+        Ok(u) => matches!(u.scheme(), "http" | "https") && u.host().is_some(),
+        Err(_) => false,
+    }
+}
+
+async fn render_list(
+    tera: &Tera,
+    state: &Context,
+    clinic: &ActiveClinic,
+    error: Option<&str>,
+) -> Result<Response, AppError> {
+    let mut c = tera::Context::new();
+
+    let hooks = webhooks::of_clinic(&state.rb, clinic.id).await?;
+
+    c.insert("webhooks", &hooks);
+    c.insert("events", webhooks::EVENTS);
+    c.insert("error", &error);
+    let r = tera.render("webhook/list.html", &c).unwrap();
+
+    Ok(Html::from(r).into_response())
+}
+
+pub async fn list(
+    Extension(tera): Extension<Tera>,
+    Extension(state): Extension<Arc<Context>>,
+    Extension(clinic): Extension<ActiveClinic>,
+    _user: User,
+) -> Result<Response, AppError> {
+    if !clinic.is_admin {
+        return Ok(Redirect::to("/").into_response());
+    }
+
+    render_list(&tera, &state, &clinic, None).await
+}
+
+pub async fn create(
+    Extension(tera): Extension<Tera>,
+    Extension(state): Extension<Arc<Context>>,
+    Extension(clinic): Extension<ActiveClinic>,
+    user: User,
+    form: Form<WebhookForm>,
+) -> Result<Response, AppError> {
+    if !clinic.is_admin {
+        return Ok(Redirect::to("/").into_response());
+    }
+
+    let url = form.url.trim();
+    let events: Vec<&str> = webhooks::EVENTS
+        .iter()
+        .copied()
+        .filter(|e| form.events.iter().any(|f| f == e))
+        .collect();
+    if !is_valid_url(url) || events.is_empty() {
+        let error = "Give an http(s) URL and at least one event.";
+        return render_list(&tera, &state, &clinic, Some(error)).await;
+    }
+
+    let secret = match form.secret.trim() {
+        "" => webhooks::new_secret(),
+        s => s.to_string(),
+    };
+    let hook = Webhook {
+        id: 0,
+        clinic_id: clinic.id,
+        url: url.to_string(),
+        events: events.join(","),
+        secret,
+        active: true,
+        created_at: Utc::now().naive_utc(),
+        created_by: user.id,
+    };
+    webhooks::save(&state.rb, &hook).await?;
+
+    Ok(Redirect::to("/webhooks").into_response())
+}
+
+/// Settings and delivery log of a webhook
+pub async fn get(
+    Extension(tera): Extension<Tera>,
+    Extension(state): Extension<Arc<Context>>,
+    Extension(clinic): Extension<ActiveClinic>,
+    _user: User,
+    Path(id): Path<u32>,
+) -> Result<Response, AppError> {
+    let hook = match webhooks::get(&state.rb, clinic.id, id).await? {
+        Some(hook) if clinic.is_admin => hook,
+        _ => return Ok(Redirect::to("/webhooks").into_response()),
+    };
+    let mut c = tera::Context::new();
+
+    let deliveries = webhooks::deliveries(&state.rb, &hook).await?;
+
+    c.insert("webhook", &hook);
+    c.insert("deliveries", &deliveries);
+    c.insert("max_attempts", &webhooks::MAX_ATTEMPTS);
+    let r = tera.render("webhook/detail.html", &c).unwrap();
+
+    Ok(Html::from(r).into_response())
+}
+
+async fn find(
+    state: &Context,
+    clinic: &ActiveClinic,
+    id: u32,
+) -> Result<Option<Webhook>, rbatis::Error> {
+    if !clinic.is_admin {
+        return Ok(None);
+    }
+
+    webhooks::get(&state.rb, clinic.id, id).await
+}
+
+pub async fn toggle(
+    Extension(state): Extension<Arc<Context>>,
+    Extension(clinic): Extension<ActiveClinic>,
+    _user: User,
+    Path(id): Path<u32>,
+) -> Result<impl IntoResponse, AppError> {
+    if let Some(mut hook) = find(&state, &clinic, id).await? {
+        hook.active = !hook.active;
+        webhooks::save(&state.rb, &hook).await?;
+    }
+    Ok(Redirect::to(&format!("/webhooks/{}", id)))
+}
+
+pub async fn delete(
+    Extension(state): Extension<Arc<Context>>,
+    Extension(clinic): Extension<ActiveClinic>,
+    _user: User,
+    Path(id): Path<u32>,
+) -> Result<impl IntoResponse, AppError> {
+    if let Some(hook) = find(&state, &clinic, id).await? {
+        webhooks::delete(&state.rb, &hook).await?;
+    }
+    Ok(Redirect::to("/webhooks"))
+}
+
+/// Queues a `ping` event, to check the receiver
+pub async fn ping(
+    Extension(state): Extension<Arc<Context>>,
+    Extension(clinic): Extension<ActiveClinic>,
+    user: User,
+    Path(id): Path<u32>,
+) -> Result<impl IntoResponse, AppError> {
+    if let Some(hook) = find(&state, &clinic, id).await? {
+        let data = json!({ "webhook_id": hook.id, "sent_by": user.username });
+        webhooks::enqueue(&state.rb, &hook, webhooks::PING, &data).await?;
+    }
+    Ok(Redirect::to(&format!("/webhooks/{}", id)))
+}
+
+pub async fn redeliver(
+    Extension(state): Extension<Arc<Context>>,
+    Extension(clinic): Extension<ActiveClinic>,
+    _user: User,
+    Path((id, delivery_id)): Path<(u32, u32)>,
+) -> Result<impl IntoResponse, AppError> {
+    if let Some(hook) = find(&state, &clinic, id).await? {
+        webhooks::redeliver(&state.rb, &hook, delivery_id).await?;
+    }
+    Ok(Redirect::to(&format!("/webhooks/{}", id)))
+}
@@ -1,3 +1,10 @@
+/// Requests a client may make in each window of `window` seconds
+#[derive(Clone, Copy, Debug)]
+pub struct RateLimit {
+    pub requests: u32,
+    pub window: u64,
+}
+
 #[derive(Clone, Debug)]
 pub struct Env {
     pub name: String,
@@ -8,6 +15,10 @@ pub struct Env {
     pub redis_server: String,
     pub redis_password: Option<String>,
     pub session_timeout: usize,
+    /// login attempts, per address and per username
+    pub login_rate_limit: RateLimit,
+    /// API and GraphQL calls, per address and per user
+    pub api_rate_limit: RateLimit,
 }
 
 pub fn from_str(env: &str) -> Env {
@@ -21,6 +32,14 @@ pub fn from_str(env: &str) -> Env {
             redis_server: "localhost".to_string(),
             redis_password: None,
             session_timeout: 108000, // 1h
+            login_rate_limit: RateLimit {
+                requests: 10,
+                window: 300,
+            },
+            api_rate_limit: RateLimit {
+                requests: 600,
+                window: 60,
+            },
         },
         "qa" => Env {
             name: "qa".to_string(),
@@ -31,6 +50,14 @@ pub fn from_str(env: &str) -> Env {
             redis_server: "localhost".to_string(),
             redis_password: None,
             session_timeout: 108000, // 1h
+            login_rate_limit: RateLimit {
+                requests: 10,
+                window: 300,
+            },
+            api_rate_limit: RateLimit {
+                requests: 600,
+                window: 60,
+            },
         },
         "prod" => Env {
             name: "prod".to_string(),
@@ -41,6 +68,14 @@ pub fn from_str(env: &str) -> Env {
             redis_server: "localhost".to_string(),
             redis_password: Some("redispass".to_string()),
             session_timeout: 108000, // 1h
+            login_rate_limit: RateLimit {
+                requests: 10,
+                window: 300,
+            },
+            api_rate_limit: RateLimit {
+                requests: 600,
+                window: 60,
+            },
         },
         _ => Env {
             name: "_".to_string(),
@@ -51,6 +86,14 @@ pub fn from_str(env: &str) -> Env {
             redis_server: "localhost".to_string(),
             redis_password: None,
             session_timeout: 108000, // 1h
+            login_rate_limit: RateLimit {
+                requests: 10,
+                window: 300,
+            },
+            api_rate_limit: RateLimit {
+                requests: 600,
+                window: 60,
+            },
         },
     }
 }
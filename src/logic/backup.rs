@@ -0,0 +1,182 @@
+//! Portable backup of the whole database as a JSON archive.
+//!
+//! The archive holds every table with its ids, so references between records
+//! survive a restore. `FORMAT_VERSION` is raised whenever its layout changes;
+//! older archives must keep restoring.
+
+use chrono::{naive::NaiveDateTime, Utc};
+use rbatis::{
+    crud::{CRUDMut, CRUDTable, CRUD},
+    executor::RBatisTxExecutor,
+    rbatis::Rbatis,
+};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    clinics::{Clinic, UserClinic},
+    ownership::OwnershipTransfer,
+    pets::Pet,
+    saved_searches::SavedSearch,
+    tokens::ApiToken,
+    users::{self, User},
+    vets::Vet,
+    visits::Visit,
+    webhooks::{Webhook, WebhookDelivery},
+};
+
+/// Value of `Archive::format`, tells our archives apart from any other JSON file
+pub const FORMAT: &str = "petclinic-backup";
+/// 2 added the webhooks, their deliveries and the API tokens
+pub const FORMAT_VERSION: u32 = 2;
+
+/// A user account, the password hash is left out when the backup was made without passwords
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ArchivedUser {
+    pub id: u32,
+    pub username: String,
+    pub password: Option<String>,
+    pub clinic_id: u32,
+    pub is_admin: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Archive {
+    pub format: String,
+    pub version: u32,
+    pub created_at: NaiveDateTime,
+    pub clinics: Vec<Clinic>,
+    pub users: Vec<ArchivedUser>,
+    pub user_clinics: Vec<UserClinic>,
+    pub vets: Vec<Vet>,
+    pub pets: Vec<Pet>,
+    pub visits: Vec<Visit>,
+    pub ownership_transfers: Vec<OwnershipTransfer>,
+    pub saved_searches: Vec<SavedSearch>,
+    #[serde(default)]
+    pub webhooks: Vec<Webhook>,
+    #[serde(default)]
+    pub webhook_deliveries: Vec<WebhookDelivery>,
+    /// the token hashes sign in like passwords, they are only archived with the passwords
+    #[serde(default)]
+    pub api_tokens: Vec<ApiToken>,
+}
+
+/// Reads every table. Without `with_passwords` the archive holds no password or token hash.
+pub async fn create(rb: &Rbatis, with_passwords: bool) -> Result<Archive, rbatis::Error> {
+    let users: Vec<User> = rb.fetch_list().await?;
+
+    Ok(Archive {
+        format: FORMAT.to_string(),
+        version: FORMAT_VERSION,
+        created_at: Utc::now().naive_utc(),
+        clinics: rb.fetch_list().await?,
+        users: users
+            .into_iter()
+            .map(|u| ArchivedUser {
+                id: u.id,
+                username: u.username,
+                password: Some(u.password).filter(|_| with_passwords),
+                clinic_id: u.clinic_id,
+                is_admin: u.is_admin,
+            })
+            .collect(),
+        user_clinics: rb.fetch_list().await?,
+        vets: rb.fetch_list().await?,
+        pets: rb.fetch_list().await?,
+        visits: rb.fetch_list().await?,
+        ownership_transfers: rb.fetch_list().await?,
+        saved_searches: rb.fetch_list().await?,
+        webhooks: rb.fetch_list().await?,
+        webhook_deliveries: rb.fetch_list().await?,
+        api_tokens: if with_passwords {
+            rb.fetch_list().await?
+        } else {
+            Vec::new()
+        },
+    })
+}
+
+async fn insert_all<T: CRUDTable>(
+    tx: &mut RBatisTxExecutor<'_>,
+    records: &[T],
+) -> Result<(), rbatis::Error> {
+    for record in records {
+        tx.save(record, &[]).await?;
+    }
+
+    Ok(())
+}
+
+/// Loads an archive into a database without any data, in a single transaction.
+/// Accounts archived without a password get `password`, or are locked when it is `None`.
+pub async fn restore(
+    rb: &Rbatis,
+    archive: &Archive,
+    password: Option<&str>,
+) -> Result<(), rbatis::Error> {
+    if archive.format != FORMAT {
+        return Err(rbatis::Error::from("not a petclinic backup"));
+    }
+    if archive.version > FORMAT_VERSION {
+        return Err(rbatis::Error::from(format!(
+            "the backup has format version {}, this petclinic only reads up to version {}",
+            archive.version, FORMAT_VERSION
+        )));
+    }
+
+    let rows: u64 = rb
+        .fetch(
+            "select (select count(*) from clinic) + (select count(*) from user) \
+             + (select count(*) from vet) + (select count(*) from pet) \
+             + (select count(*) from visit)",
+            vec![],
+        )
+        .await?;
+    if rows > 0 {
+        return Err(rbatis::Error::from(
+            "the database is not empty, restore into a newly created schema",
+        ));
+    }
+
+    let users: Vec<User> = archive
+        .users
+        .iter()
+        .map(|u| User {
+            id: u.id,
+            username: u.username.clone(),
+            password: match (&u.password, password) {
+                (Some(hash), _) => hash.clone(),
+                (None, Some(password)) => users::hash_password(password),
+                // no password hashes to a value that is not hexadecimal
+                (None, None) => format!("!{}", users::session_key()),
+            },
+            clinic_id: u.clinic_id,
+            is_admin: u.is_admin,
+        })
+        .collect();
+
+    let mut tx = rb.acquire_begin().await?;
+
+    let result = async {
+        insert_all(&mut tx, &archive.clinics).await?;
+        insert_all(&mut tx, &users).await?;
+        insert_all(&mut tx, &archive.user_clinics).await?;
+        insert_all(&mut tx, &archive.vets).await?;
+        insert_all(&mut tx, &archive.pets).await?;
+        insert_all(&mut tx, &archive.visits).await?;
+        insert_all(&mut tx, &archive.ownership_transfers).await?;
+        insert_all(&mut tx, &archive.saved_searches).await?;
+        insert_all(&mut tx, &archive.webhooks).await?;
+        insert_all(&mut tx, &archive.webhook_deliveries).await?;
+        insert_all(&mut tx, &archive.api_tokens).await
+    }
+    .await;
+
+    match result {
+        Ok(_) => tx.commit().await,
+        Err(e) => {
+            tx.rollback().await?;
+            Err(e)
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use rbatis::{crud::CRUD, crud_table, rbatis::Rbatis, wrapper::Wrapper};
+use rbson::Bson;
+
+use super::users::User;
+
+#[crud_table]
+#[derive(Default, Clone)]
+pub struct Clinic {
+    pub id: u32,
+    pub name: String,
+}
+
+/// An additional clinic a user works at, besides their home clinic
+#[crud_table]
+#[derive(Default, Clone)]
+pub struct UserClinic {
+    pub user_id: u32,
+    pub clinic_id: u32,
+}
+
+/// The clinic a session is working on, put in the request extensions by the `User` extractor
+#[derive(Clone, Copy, Debug)]
+pub struct ActiveClinic {
+    pub id: u32,
+    pub is_admin: bool,
+}
+
+/// Which clinics a query may look at
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Scope {
+    Clinic(u32),
+    /// Cross-clinic lookup, only granted to admins
+    All,
+}
+
+impl Scope {
+    pub fn filter(&self, w: Wrapper) -> Wrapper {
+        match self {
+            Scope::Clinic(id) => w.eq("clinic_id", id),
+            Scope::All => w,
+        }
+    }
+
+    /// Same as `filter` for raw SQL: an ` and <column> = ?` clause, its argument pushed to `args`
+    pub fn sql(&self, column: &str, args: &mut Vec<Bson>) -> String {
+        match self {
+            Scope::Clinic(id) => {
+                args.push(Bson::from(*id));
+                format!(" and {} = ?", column)
+            }
+            Scope::All => String::new(),
+        }
+    }
+
+    pub fn is_all(&self) -> bool {
+        *self == Scope::All
+    }
+}
+
+pub fn redis_key(session: &str) -> String {
+    format!("{}:clinic", session)
+}
+
+/// Clinics the user can switch to: every clinic for admins, otherwise their
+/// home clinic plus the ones they are linked to in `user_clinic`
+pub async fn of_user(rb: &Rbatis, user: &User) -> Result<Vec<Clinic>, rbatis::Error> {
+    if user.is_admin {
+        let w = rb.new_wrapper().order_by(true, &["name"]);
+        return rb.fetch_list_by_wrapper(w).await;
+    }
+
+    rb.fetch(
+        "select * from clinic where id = ? \
+         or id in (select clinic_id from user_clinic where user_id = ?) order by name",
+        vec![Bson::from(user.clinic_id), Bson::from(user.id)],
+    )
+    .await
+}
+
+pub async fn can_access(rb: &Rbatis, user: &User, clinic_id: u32) -> Result<bool, rbatis::Error> {
+    if user.is_admin || user.clinic_id == clinic_id {
+        return Ok(true);
+    }
+    let clinics = of_user(rb, user).await?;
+
+    Ok(clinics.iter().any(|c| c.id == clinic_id))
+}
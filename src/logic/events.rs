@@ -0,0 +1,119 @@
+use redis::{Client, Commands};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+use super::{pets::Pet, vets::Vet};
+use crate::Context;
+
+use std::{thread, time::Duration};
+
+/// Redis channel every instance publishes its changes to and listens on
+const CHANNEL: &str = "petclinic:changes";
+/// Changes kept for slow listeners, they are told to reload when they miss some
+pub const BUFFER_SIZE: usize = 256;
+/// Wait before subscribing again after losing the Redis connection
+const RECONNECT_SECS: u64 = 2;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Pet,
+    Vet,
+}
+
+impl Kind {
+    /// Name of the SSE event
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Kind::Pet => "pet",
+            Kind::Vet => "vet",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Created,
+    Updated,
+    Deleted,
+}
+
+/// A pet or vet that was changed, as sent to the pages listening on `/events`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Change {
+    pub kind: Kind,
+    pub action: Action,
+    pub id: u32,
+    pub clinic_id: u32,
+    /// the record after the change, before it for deletions
+    pub data: serde_json::Value,
+}
+
+impl Change {
+    pub fn pet(action: Action, pet: &Pet) -> Change {
+        Change {
+            kind: Kind::Pet,
+            action,
+            id: pet.id,
+            clinic_id: pet.clinic_id,
+            data: serde_json::to_value(pet).unwrap_or_default(),
+        }
+    }
+
+    pub fn vet(action: Action, vet: &Vet) -> Change {
+        Change {
+            kind: Kind::Vet,
+            action,
+            id: vet.id,
+            clinic_id: vet.clinic_id,
+            data: serde_json::to_value(vet).unwrap_or_default(),
+        }
+    }
+}
+
+/// Sends the change to the listeners of every instance through Redis. When Redis can't
+/// be reached, at least the listeners of this instance get it.
+pub fn publish(state: &Context, change: Change) {
+    let message = match serde_json::to_string(&change) {
+        Ok(message) => message,
+        Err(_) => return,
+    };
+    let published: redis::RedisResult<u32> = state
+        .redis_connection
+        .lock()
+        .unwrap()
+        .publish(CHANNEL, message);
+
+    if let Err(e) = published {
+        tracing::error!("Could not publish change event: {}", e);
+        let _ = state.changes.send(change);
+    }
+}
+
+/// Forwards the changes published on Redis to `sender`, on a thread of its own since the
+/// Redis subscription blocks
+pub fn subscribe(client: Client, sender: broadcast::Sender<Change>) {
+    thread::spawn(move || loop {
+        if let Err(e) = listen(&client, &sender) {
+            tracing::error!("Change events subscription lost: {}", e);
+        }
+        thread::sleep(Duration::from_secs(RECONNECT_SECS));
+    });
+}
+
+fn listen(client: &Client, sender: &broadcast::Sender<Change>) -> redis::RedisResult<()> {
+    let mut connection = client.get_connection()?;
+    let mut pubsub = connection.as_pubsub();
+    pubsub.subscribe(CHANNEL)?;
+
+    loop {
+        let message = pubsub.get_message()?;
+        let payload: String = message.get_payload()?;
+        match serde_json::from_str::<Change>(&payload) {
+            // no listeners is fine
+            Ok(change) => drop(sender.send(change)),
+            Err(e) => tracing::error!("Unreadable change event: {}", e),
+        }
+    }
+}
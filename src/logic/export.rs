@@ -0,0 +1,243 @@
+use std::{borrow::Cow, collections::HashMap};
+
+use rust_xlsxwriter::{Format as CellFormat, Workbook, XlsxError};
+use serde::Serialize;
+
+use super::{
+    pets::{self, Pet},
+    stats::Workload,
+    vets::Vet,
+    visits::VisitDetail,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Csv,
+    Xlsx,
+}
+
+impl Format {
+    pub fn from_param(format: &str) -> Format {
+        match format {
+            "xlsx" => Format::Xlsx,
+            _ => Format::Csv,
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Format::Csv => "text/csv; charset=utf-8",
+            Format::Xlsx => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Csv => "csv",
+            Format::Xlsx => "xlsx",
+        }
+    }
+}
+
+/// An exportable column, `key` is what the export form sends
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Column {
+    pub key: &'static str,
+    pub label: &'static str,
+}
+
+pub const PET_COLUMNS: &[Column] = &[
+    Column {
+        key: "id",
+        label: "Id",
+    },
+    Column {
+        key: "name",
+        label: "Name",
+    },
+    Column {
+        key: "pet_type",
+        label: "Type",
+    },
+    Column {
+        key: "age",
+        label: "Age",
+    },
+    Column {
+        key: "owner_name",
+        label: "Owner name",
+    },
+    Column {
+        key: "owner_phone",
+        label: "Owner phone",
+    },
+    Column {
+        key: "vet",
+        label: "Vet",
+    },
+    Column {
+        key: "created_at",
+        label: "Registered",
+    },
+];
+
+pub const VET_COLUMNS: &[Column] = &[
+    Column {
+        key: "id",
+        label: "Id",
+    },
+    Column {
+        key: "name",
+        label: "Name",
+    },
+];
+
+pub const VISIT_COLUMNS: &[Column] = &[
+    Column {
+        key: "visit_date",
+        label: "Date",
+    },
+    Column {
+        key: "pet",
+        label: "Pet",
+    },
+    Column {
+        key: "vet",
+        label: "Vet",
+    },
+    Column {
+        key: "notes",
+        label: "Notes",
+    },
+];
+
+pub const WORKLOAD_COLUMNS: &[Column] = &[
+    Column {
+        key: "name",
+        label: "Vet",
+    },
+    Column {
+        key: "pets",
+        label: "Assigned pets",
+    },
+    Column {
+        key: "visits",
+        label: "Visits",
+    },
+    Column {
+        key: "visits_per_day",
+        label: "Visits per day",
+    },
+];
+
+/// Keeps the requested columns in table order, or all of them when none was requested
+pub fn select(available: &'static [Column], requested: &[String]) -> Vec<&'static Column> {
+    let selected: Vec<&Column> = available
+        .iter()
+        .filter(|c| requested.iter().any(|r| r == c.key))
+        .collect();
+
+    if selected.is_empty() {
+        available.iter().collect()
+    } else {
+        selected
+    }
+}
+
+pub fn pet_row(pet: &Pet, columns: &[&Column], vet_names: &HashMap<u32, String>) -> Vec<String> {
+    columns
+        .iter()
+        .map(|c| match c.key {
+            "id" => pet.id.to_string(),
+            "name" => pet.name.clone(),
+            "pet_type" => pets::type_label(pet.pet_type),
+            "age" => pet.age.to_string(),
+            "owner_name" => pet.owner_name.clone(),
+            "owner_phone" => pet.owner_phone.clone(),
+            "vet" => pet
+                .vet_id
+                .and_then(|id| vet_names.get(&id).cloned())
+                .unwrap_or_default(),
+            "created_at" => pet.created_at.format("%Y-%m-%d %H:%M").to_string(),
+            _ => String::new(),
+        })
+        .collect()
+}
+
+pub fn vet_row(vet: &Vet, columns: &[&Column]) -> Vec<String> {
+    columns
+        .iter()
+        .map(|c| match c.key {
+            "id" => vet.id.to_string(),
+            "name" => vet.name.clone(),
+            _ => String::new(),
+        })
+        .collect()
+}
+
+pub fn visit_row(visit: &VisitDetail, columns: &[&Column]) -> Vec<String> {
+    columns
+        .iter()
+        .map(|c| match c.key {
+            "visit_date" => visit.visit_date.format("%Y-%m-%d %H:%M").to_string(),
+            "pet" => visit.pet_name.clone().unwrap_or_default(),
+            "vet" => visit.vet_name.clone().unwrap_or_default(),
+            "notes" => visit.notes.clone().unwrap_or_default(),
+            _ => String::new(),
+        })
+        .collect()
+}
+
+pub fn workload_row(workload: &Workload, columns: &[&Column]) -> Vec<String> {
+    columns
+        .iter()
+        .map(|c| match c.key {
+            "name" => workload.name.clone(),
+            "pets" => workload.pets.to_string(),
+            "visits" => workload.visits.to_string(),
+            "visits_per_day" => format!("{:.2}", workload.visits_per_day),
+            _ => String::new(),
+        })
+        .collect()
+}
+
+/// Prefixes with `'` the values a spreadsheet would run as a formula
+pub fn inert(value: &str) -> Cow<'_, str> {
+    match value.chars().next() {
+        Some('=' | '+' | '-' | '@' | '\t' | '\r') => Cow::Owned(format!("'{}", value)),
+        _ => Cow::Borrowed(value),
+    }
+}
+
+/// Encodes rows as CSV, starting with the header line when `header` is set
+pub fn csv(columns: &[&Column], rows: &[Vec<String>], header: bool) -> Result<Vec<u8>, csv::Error> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    if header {
+        writer.write_record(columns.iter().map(|c| c.label))?;
+    }
+    for row in rows {
+        let row: Vec<Cow<str>> = row.iter().map(|value| inert(value)).collect();
+        writer.write_record(row.iter().map(|value| value.as_bytes()))?;
+    }
+
+    writer.into_inner().map_err(|e| e.into_error().into())
+}
+
+pub fn xlsx(sheet: &str, columns: &[&Column], rows: &[Vec<String>]) -> Result<Vec<u8>, XlsxError> {
+    let mut workbook = Workbook::new();
+    let bold = CellFormat::new().set_bold();
+
+    let worksheet = workbook.add_worksheet();
+    worksheet.set_name(sheet)?;
+    for (col, column) in columns.iter().enumerate() {
+        worksheet.write_string_with_format(0, col as u16, column.label, &bold)?;
+    }
+    for (row, values) in rows.iter().enumerate() {
+        for (col, value) in values.iter().enumerate() {
+            worksheet.write_string(row as u32 + 1, col as u16, value)?;
+        }
+    }
+    worksheet.autofit();
+
+    workbook.save_to_buffer()
+}
@@ -0,0 +1,400 @@
+//! CSV import of pets and vets.
+//!
+//! The file is read and every row validated first, the records are only
+//! written when the whole file is valid, in a single transaction.
+
+use std::collections::HashMap;
+
+use chrono::Utc;
+use rbatis::{
+    crud::{CRUDMut, CRUDTable},
+    rbatis::Rbatis,
+};
+use serde::Serialize;
+
+use super::{
+    clinics::Scope,
+    export,
+    pets::{self, Pet},
+    vets::{self, Vet},
+};
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Pets,
+    Vets,
+}
+
+impl Kind {
+    pub fn from_param(kind: &str) -> Kind {
+        match kind {
+            "vets" => Kind::Vets,
+            _ => Kind::Pets,
+        }
+    }
+
+    pub fn fields(&self) -> &'static [Field] {
+        match self {
+            Kind::Pets => PET_FIELDS,
+            Kind::Vets => VET_FIELDS,
+        }
+    }
+}
+
+/// A value the import can read from a CSV column
+#[derive(Serialize, Debug)]
+pub struct Field {
+    pub key: &'static str,
+    pub label: &'static str,
+    pub required: bool,
+}
+
+pub const PET_FIELDS: &[Field] = &[
+    Field {
+        key: "name",
+        label: "Name",
+        required: true,
+    },
+    Field {
+        key: "pet_type",
+        label: "Type",
+        required: true,
+    },
+    Field {
+        key: "age",
+        label: "Age",
+        required: false,
+    },
+    Field {
+        key: "owner_name",
+        label: "Owner name",
+        required: true,
+    },
+    Field {
+        key: "owner_phone",
+        label: "Owner phone",
+        required: false,
+    },
+    Field {
+        key: "vet",
+        label: "Vet",
+        required: false,
+    },
+];
+
+pub const VET_FIELDS: &[Field] = &[Field {
+    key: "name",
+    label: "Name",
+    required: true,
+}];
+
+/// Content of an uploaded CSV file
+#[derive(Serialize, Debug)]
+pub struct Upload {
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+    /// line of the file each row starts on, quoted values may span several lines
+    pub lines: Vec<usize>,
+}
+
+/// CSV column index of each mapped field
+pub type Mapping = HashMap<String, usize>;
+
+pub fn read(content: &str) -> Result<Upload, csv::Error> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(content.trim_start_matches('\u{feff}').as_bytes());
+
+    let headers = reader.headers()?.iter().map(String::from).collect();
+    let mut rows = Vec::new();
+    let mut lines = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        lines.push(
+            record
+                .position()
+                .map(|p| p.line() as usize)
+                .unwrap_or_default(),
+        );
+        rows.push(record.iter().map(String::from).collect());
+    }
+
+    Ok(Upload {
+        headers,
+        rows,
+        lines,
+    })
+}
+
+fn simplify(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric())
+        .collect::<String>()
+        .to_lowercase()
+}
+
+/// Maps the columns whose header looks like a field key or label, e.g. "Owner Phone" or "owner_phone"
+pub fn guess_mapping(fields: &[Field], headers: &[String]) -> Mapping {
+    fields
+        .iter()
+        .filter_map(|f| {
+            headers
+                .iter()
+                .position(|h| simplify(h) == simplify(f.key) || simplify(h) == simplify(f.label))
+                .map(|index| (f.key.to_string(), index))
+        })
+        .collect()
+}
+
+/// Labels of the required fields without a column
+pub fn missing_fields(fields: &[Field], mapping: &Mapping) -> Vec<&'static str> {
+    fields
+        .iter()
+        .filter(|f| f.required && !mapping.contains_key(f.key))
+        .map(|f| f.label)
+        .collect()
+}
+
+/// A problem found in a row, `line` counts the header as line 1
+#[derive(Serialize, Clone, Debug)]
+pub struct RowError {
+    pub line: usize,
+    pub field: String,
+    pub value: String,
+    pub message: String,
+}
+
+/// Records ready to be saved and the problems found, nothing is saved unless `errors` is empty
+#[derive(Debug)]
+pub struct Validated<T> {
+    pub records: Vec<T>,
+    pub errors: Vec<RowError>,
+}
+
+struct Row<'a> {
+    line: usize,
+    values: &'a [String],
+    mapping: &'a Mapping,
+    errors: Vec<RowError>,
+}
+
+impl<'a> Row<'a> {
+    fn value(&self, field: &str) -> &'a str {
+        self.mapping
+            .get(field)
+            .and_then(|index| self.values.get(*index))
+            .map(|v| v.as_str())
+            .unwrap_or("")
+    }
+
+    fn error(&mut self, field: &str, message: String) {
+        self.errors.push(RowError {
+            line: self.line,
+            field: field.to_string(),
+            value: self.value(field).to_string(),
+            message,
+        });
+    }
+
+    fn required(&mut self, field: &str) -> String {
+        let value = self.value(field);
+        if value.is_empty() {
+            self.error(field, "is required".to_string());
+        }
+        value.to_string()
+    }
+
+    /// Required name, no longer than the database column
+    fn name(&mut self, field: &str) -> String {
+        let value = self.required(field);
+        if value.chars().count() > pets::MAX_NAME_CHARS {
+            self.error(
+                field,
+                format!("is longer than {} characters", pets::MAX_NAME_CHARS),
+            );
+        }
+        value
+    }
+}
+
+fn rows<'a>(upload: &'a Upload, mapping: &'a Mapping) -> impl Iterator<Item = Row<'a>> {
+    upload
+        .rows
+        .iter()
+        .zip(&upload.lines)
+        .filter(|(values, _)| values.iter().any(|v| !v.is_empty()))
+        .map(move |(values, line)| Row {
+            line: *line,
+            values,
+            mapping,
+            errors: Vec::new(),
+        })
+}
+
+/// Checks the pets of the file, vets are looked up by name among the active vets of the clinic
+pub async fn validate_pets(
+    rb: &Rbatis,
+    clinic_id: u32,
+    user_id: u32,
+    upload: &Upload,
+    mapping: &Mapping,
+) -> Result<Validated<Pet>, rbatis::Error> {
+    let vets_by_name: HashMap<String, u32> = vets::active(rb, &Scope::Clinic(clinic_id))
+        .await?
+        .into_iter()
+        .map(|v| (v.name.trim().to_lowercase(), v.id))
+        .collect();
+
+    let mut validated = Validated {
+        records: Vec::new(),
+        errors: Vec::new(),
+    };
+    for mut row in rows(upload, mapping) {
+        let name = row.name("name");
+        let owner_name = row.name("owner_name");
+
+        let type_value = row.required("pet_type");
+        let pet_type = pets::type_by_name(&type_value);
+        if pet_type.is_none() && !type_value.is_empty() {
+            row.error(
+                "pet_type",
+                format!(
+                    "unknown pet type, expected one of: {}",
+                    pets::type_names().join(", ")
+                ),
+            );
+        }
+
+        let age = match row.value("age") {
+            "" => 0,
+            value => match value.parse() {
+                Ok(age) if age <= pets::MAX_AGE => age,
+                Ok(_) => {
+                    row.error("age", format!("must be at most {}", pets::MAX_AGE));
+                    0
+                }
+                Err(_) => {
+                    row.error("age", "must be a whole number".to_string());
+                    0
+                }
+            },
+        };
+
+        let owner_phone = row.value("owner_phone").to_string();
+        if !owner_phone.is_empty() && !pets::is_valid_phone(&owner_phone) {
+            row.error(
+                "owner_phone",
+                format!(
+                    "bad phone number, expected {} to {} digits",
+                    pets::MIN_PHONE_DIGITS,
+                    pets::MAX_PHONE_DIGITS
+                ),
+            );
+        }
+
+        let vet_id = match row.value("vet") {
+            "" => None,
+            vet => {
+                let id = vets_by_name.get(&vet.to_lowercase()).copied();
+                if id.is_none() {
+                    row.error("vet", "unknown vet in this clinic".to_string());
+                }
+                id
+            }
+        };
+
+        if row.errors.is_empty() {
+            validated.records.push(Pet {
+                name,
+                owner_name,
+                owner_phone,
+                age,
+                pet_type: pet_type.unwrap_or_default(),
+                vet_id,
+                created_at: Utc::now().naive_utc(),
+                created_by: user_id,
+                clinic_id,
+                ..Default::default()
+            });
+        }
+        validated.errors.append(&mut row.errors);
+    }
+
+    Ok(validated)
+}
+
+/// Checks the vets of the file, names must not be repeated nor exist already in the clinic
+pub async fn validate_vets(
+    rb: &Rbatis,
+    clinic_id: u32,
+    upload: &Upload,
+    mapping: &Mapping,
+) -> Result<Validated<Vet>, rbatis::Error> {
+    let mut known: Vec<String> = vets::active(rb, &Scope::Clinic(clinic_id))
+        .await?
+        .into_iter()
+        .map(|v| v.name.trim().to_lowercase())
+        .collect();
+
+    let mut validated = Validated {
+        records: Vec::new(),
+        errors: Vec::new(),
+    };
+    for mut row in rows(upload, mapping) {
+        let name = row.name("name");
+        if known.contains(&name.to_lowercase()) {
+            row.error("name", "a vet with this name already exists".to_string());
+        }
+
+        if row.errors.is_empty() {
+            known.push(name.to_lowercase());
+            validated.records.push(Vet {
+                name,
+                active: true,
+                clinic_id,
+                ..Default::default()
+            });
+        }
+        validated.errors.append(&mut row.errors);
+    }
+
+    Ok(validated)
+}
+
+/// Saves all the records or none of them
+pub async fn commit<T: CRUDTable>(rb: &Rbatis, records: &[T]) -> Result<(), rbatis::Error> {
+    let mut tx = rb.acquire_begin().await?;
+
+    let result = async {
+        for record in records {
+            tx.save(record, &[]).await?;
+        }
+        Ok(())
+    }
+    .await;
+
+    match result {
+        Ok(_) => tx.commit().await,
+        Err(e) => {
+            tx.rollback().await?;
+            Err(e)
+        }
+    }
+}
+
+pub fn error_report(errors: &[RowError]) -> Result<Vec<u8>, csv::Error> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(["line", "field", "value", "error"])?;
+    for e in errors {
+        writer.write_record([
+            &e.line.to_string(),
+            &e.field,
+            export::inert(&e.value).as_ref(),
+            &e.message,
+        ])?;
+    }
+
+    writer.into_inner().map_err(|e| e.into_error().into())
+}
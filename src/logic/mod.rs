@@ -1,3 +1,20 @@
+pub mod backup;
+pub mod clinics;
+pub mod events;
+pub mod export;
+pub mod import;
+pub mod ownership;
+pub mod paging;
+pub mod pet_query;
 pub mod pets;
+pub mod rate_limit;
+pub mod saved_searches;
+pub mod search;
+pub mod spring;
+pub mod sql_dump;
+pub mod stats;
+pub mod tokens;
 pub mod users;
 pub mod vets;
+pub mod visits;
+pub mod webhooks;
@@ -0,0 +1,109 @@
+use chrono::{naive::NaiveDateTime, Utc};
+use rbatis::{
+    crud::{CRUDMut, CRUD},
+    crud_table,
+    rbatis::Rbatis,
+};
+
+use super::pets::{self, Pet};
+
+/// A change of owner of a pet, kept so previous owners are not lost
+#[crud_table]
+#[derive(Default, Clone)]
+pub struct OwnershipTransfer {
+    pub id: u32,
+    pub pet_id: u32,
+    pub previous_owner_name: String,
+    pub previous_owner_phone: String,
+    pub new_owner_name: String,
+    pub new_owner_phone: String,
+    pub reason: String,
+    pub transferred_at: NaiveDateTime,
+    pub transferred_by: u32,
+}
+
+pub async fn history(rb: &Rbatis, pet: &Pet) -> Result<Vec<OwnershipTransfer>, rbatis::Error> {
+    let w = rb
+        .new_wrapper()
+        .eq("pet_id", pet.id)
+        .order_by(false, &["transferred_at", "id"]);
+
+    let transfers: Vec<OwnershipTransfer> = rb.fetch_list_by_wrapper(w).await?;
+
+    Ok(transfers)
+}
+
+/// Tells what is wrong with the new owner of a transfer, if anything
+pub fn check(new_owner_name: &str, new_owner_phone: &str, reason: &str) -> Option<&'static str> {
+    if new_owner_name.trim().is_empty() {
+        Some("The name of the new owner is required")
+    } else if !new_owner_phone.trim().is_empty() && !pets::is_valid_phone(new_owner_phone.trim()) {
+        Some("The phone of the new owner is not a valid phone number")
+    } else if reason.trim().is_empty() {
+        Some("The reason of the transfer is required")
+    } else {
+        None
+    }
+}
+
+/// Hands `pet` over to a new owner, recording the previous one in the history
+pub async fn transfer(
+    rb: &Rbatis,
+    pet: &Pet,
+    new_owner_name: &str,
+    new_owner_phone: &str,
+    reason: &str,
+    user_id: u32,
+) -> Result<(), rbatis::Error> {
+    let updated = Pet {
+        owner_name: new_owner_name.to_string(),
+        owner_phone: new_owner_phone.to_string(),
+        ..pet.clone()
+    };
+
+    save_with_transfer(rb, pet, &updated, reason, user_id).await
+}
+
+/// Saves `updated`, a change of `current` that may touch more than its owner, in the same
+/// transaction as the transfer to its owner
+pub async fn save_with_transfer(
+    rb: &Rbatis,
+    current: &Pet,
+    updated: &Pet,
+    reason: &str,
+    user_id: u32,
+) -> Result<(), rbatis::Error> {
+    let record = OwnershipTransfer {
+        id: 0,
+        pet_id: current.id,
+        previous_owner_name: current.owner_name.clone(),
+        previous_owner_phone: current.owner_phone.clone(),
+        new_owner_name: updated.owner_name.clone(),
+        new_owner_phone: updated.owner_phone.clone(),
+        reason: reason.to_string(),
+        transferred_at: Utc::now().naive_utc(),
+        transferred_by: user_id,
+    };
+
+    let updated = Pet {
+        updated_at: Some(record.transferred_at),
+        ..updated.clone()
+    };
+
+    let mut tx = rb.acquire_begin().await?;
+
+    let result = async {
+        tx.save(&record, &[]).await?;
+        let w = rb.new_wrapper().eq("id", current.id);
+        tx.update_by_wrapper(&updated, w, &[]).await
+    }
+    .await;
+
+    match result {
+        Ok(_) => tx.commit().await,
+        Err(e) => {
+            tx.rollback().await?;
+            Err(e)
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+
+use rbatis::{plugin::page::PageRequest, wrapper::Wrapper};
+use serde::Serialize;
+use utoipa::IntoParams;
+
+pub const DEFAULT_PAGE_SIZE: u64 = 20;
+pub const MAX_PAGE_SIZE: u64 = 200;
+/// Highest page number, so the offset of any page fits in a u64
+pub const MAX_PAGE: u64 = u64::MAX / MAX_PAGE_SIZE;
+
+/// Page and ordering requested through the query string of a list view
+#[derive(Serialize, Clone, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct Paging {
+    #[param(value_type = Option<u64>, default = 1)]
+    pub page: u64,
+    #[param(value_type = Option<u64>, default = 20, maximum = 200)]
+    pub page_size: u64,
+    /// column to order by
+    #[param(value_type = Option<String>)]
+    pub sort: String,
+    /// "asc" or "desc"
+    #[param(value_type = Option<String>, default = "asc")]
+    pub direction: String,
+}
+
+impl Paging {
+    /// Reads `page`, `page_size`, `sort` and `direction`. Only columns in `sortable`
+    /// can be used to order the list, the first one being the default.
+    pub fn from_query(params: &HashMap<String, String>, sortable: &[&str]) -> Paging {
+        let page = params
+            .get("page")
+            .and_then(|p| p.parse().ok())
+            .filter(|p| *p > 0)
+            .unwrap_or(1)
+            .min(MAX_PAGE);
+        let page_size = params
+            .get("page_size")
+            .and_then(|p| p.parse().ok())
+            .filter(|p| *p > 0)
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .min(MAX_PAGE_SIZE);
+        let sort = params
+            .get("sort")
+            .filter(|s| sortable.contains(&s.as_str()))
+            .cloned()
+            .unwrap_or_else(|| sortable[0].to_string());
+        let direction = match params.get("direction").map(|d| d.as_str()) {
+            Some("desc") => "desc",
+            _ => "asc",
+        };
+
+        Paging {
+            page,
+            page_size,
+            sort,
+            direction: direction.to_string(),
+        }
+    }
+
+    pub fn is_asc(&self) -> bool {
+        self.direction == "asc"
+    }
+
+    /// Adds the ORDER BY clause, using the id as tie breaker so pages are stable
+    pub fn order(&self, w: Wrapper) -> Wrapper {
+        w.order_bys(&[(self.sort.as_str(), self.is_asc()), ("id", self.is_asc())])
+    }
+
+    pub fn request(&self) -> PageRequest {
+        PageRequest::new(self.page, self.page_size)
+    }
+}
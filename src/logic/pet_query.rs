@@ -0,0 +1,425 @@
+//! Query language of the pet search box, e.g. `type:dog vet:carter age>5 owner:"falk"`.
+//!
+//! Bare words and quoted strings search the pet name. Fields are written
+//! `field:value`; `age` and `created` also accept `>`, `>=`, `<`, `<=` and `=`.
+
+use std::fmt;
+
+use chrono::{naive::NaiveDate, Duration};
+use rbatis::wrapper::Wrapper;
+use serde::Serialize;
+
+use super::pets;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Comparison {
+    Eq,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum VetRef {
+    Name(String),
+    Unassigned,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Term {
+    Name(String),
+    Type(u32),
+    Vet(VetRef),
+    Owner(String),
+    Phone(String),
+    Age(Comparison, u32),
+    Created(Comparison, NaiveDate),
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PetQuery {
+    pub terms: Vec<Term>,
+}
+
+/// A malformed query, `position` is the character offset where the problem was found
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ParseError {
+    pub message: String,
+    pub position: usize,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (at character {})", self.message, self.position + 1)
+    }
+}
+
+const FIELDS: &[&str] = &["name", "type", "vet", "owner", "phone", "age", "created"];
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn error<T>(&self, position: usize, message: String) -> Result<T, ParseError> {
+        Err(ParseError { message, position })
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn quoted(&mut self) -> Result<String, ParseError> {
+        let start = self.pos;
+        self.pos += 1;
+        let mut value = String::new();
+        loop {
+            match self.peek() {
+                Some('"') => {
+                    self.pos += 1;
+                    return Ok(value);
+                }
+                Some(c) => {
+                    value.push(c);
+                    self.pos += 1;
+                }
+                None => return self.error(start, "unterminated quote".to_string()),
+            }
+        }
+    }
+
+    fn word(&mut self) -> String {
+        let mut value = String::new();
+        while let Some(c) = self.peek().filter(|c| !c.is_whitespace()) {
+            value.push(c);
+            self.pos += 1;
+        }
+        value
+    }
+
+    fn operator(&mut self) -> Option<(String, Comparison)> {
+        let (op, comparison) = match (self.peek(), self.chars.get(self.pos + 1)) {
+            (Some(':'), _) => (":", Comparison::Eq),
+            (Some('='), _) => ("=", Comparison::Eq),
+            (Some('>'), Some('=')) => (">=", Comparison::Ge),
+            (Some('>'), _) => (">", Comparison::Gt),
+            (Some('<'), Some('=')) => ("<=", Comparison::Le),
+            (Some('<'), _) => ("<", Comparison::Lt),
+            _ => return None,
+        };
+        self.pos += op.len();
+        Some((op.to_string(), comparison))
+    }
+
+    fn term(&mut self) -> Result<Term, ParseError> {
+        let start = self.pos;
+        if self.peek() == Some('"') {
+            return Ok(Term::Name(self.quoted()?));
+        }
+
+        let mut field = String::new();
+        while let Some(c) = self.peek().filter(|c| c.is_alphanumeric() || *c == '_') {
+            field.push(c);
+            self.pos += 1;
+        }
+
+        let (op, comparison) = match self.operator() {
+            Some(op) if !field.is_empty() => op,
+            _ => {
+                // not a field, the whole word is a name
+                self.pos = start;
+                return Ok(Term::Name(self.word()));
+            }
+        };
+
+        let field = field.to_lowercase();
+        if !FIELDS.contains(&field.as_str()) {
+            return self.error(
+                start,
+                format!(
+                    "unknown field `{}`, expected one of: {}",
+                    field,
+                    FIELDS.join(", ")
+                ),
+            );
+        }
+
+        let value_start = self.pos;
+        let value = match self.peek() {
+            Some('"') => self.quoted()?,
+            _ => self.word(),
+        };
+        if value.is_empty() {
+            return self.error(
+                value_start,
+                format!("missing value after `{}{}`", field, op),
+            );
+        }
+
+        if comparison != Comparison::Eq && field != "age" && field != "created" {
+            return self.error(
+                start,
+                format!("`{}` only supports `:`, use `{}:{}`", field, field, value),
+            );
+        }
+
+        match field.as_str() {
+            "name" => Ok(Term::Name(value)),
+            "owner" => Ok(Term::Owner(value)),
+            "phone" => Ok(Term::Phone(value)),
+            "vet" if value.eq_ignore_ascii_case("none") => Ok(Term::Vet(VetRef::Unassigned)),
+            "vet" => Ok(Term::Vet(VetRef::Name(value))),
+            "type" => match pets::type_by_name(&value) {
+                Some(id) => Ok(Term::Type(id)),
+                None => self.error(
+                    value_start,
+                    format!(
+                        "unknown pet type `{}`, expected one of: {}",
+                        value,
+                        pets::type_names().join(", ")
+                    ),
+                ),
+            },
+            "age" => match value.parse() {
+                Ok(age) => Ok(Term::Age(comparison, age)),
+                Err(_) => self.error(
+                    value_start,
+                    format!("age must be a whole number, found `{}`", value),
+                ),
+            },
+            _ => match NaiveDate::parse_from_str(&value, "%Y-%m-%d") {
+                Ok(date) => Ok(Term::Created(comparison, date)),
+                Err(_) => self.error(
+                    value_start,
+                    format!("dates are written YYYY-MM-DD, found `{}`", value),
+                ),
+            },
+        }
+    }
+}
+
+pub fn parse(input: &str) -> Result<PetQuery, ParseError> {
+    let mut parser = Parser {
+        chars: input.chars().collect(),
+        pos: 0,
+    };
+    let mut terms = Vec::new();
+
+    loop {
+        parser.skip_whitespace();
+        if parser.peek().is_none() {
+            break;
+        }
+        terms.push(parser.term()?);
+    }
+
+    Ok(PetQuery { terms })
+}
+
+fn compare(w: Wrapper, column: &str, comparison: Comparison, value: impl Serialize) -> Wrapper {
+    match comparison {
+        Comparison::Eq => w.eq(column, value),
+        Comparison::Gt => w.gt(column, value),
+        Comparison::Ge => w.ge(column, value),
+        Comparison::Lt => w.lt(column, value),
+        Comparison::Le => w.le(column, value),
+    }
+}
+
+impl PetQuery {
+    /// Adds the conditions to a wrapper over the `pet` table, every value is bound as an argument
+    pub fn apply(&self, mut w: Wrapper) -> Wrapper {
+        for term in &self.terms {
+            w = match term {
+                Term::Name(name) => w.like("name", name),
+                Term::Type(id) => w.eq("pet_type", id),
+                Term::Vet(VetRef::Unassigned) => w.is_null("vet_id"),
+                Term::Vet(VetRef::Name(name)) => w
+                    .and()
+                    .push_sql("vet_id in (select id from vet where name like ?)")
+                    .push_arg(format!("%{}%", name)),
+                Term::Owner(owner) => w.like("owner_name", owner),
+                Term::Phone(phone) => w.like("owner_phone", phone),
+                Term::Age(comparison, age) => compare(w, "age", *comparison, age),
+                Term::Created(comparison, date) => {
+                    let day = date.and_hms(0, 0, 0);
+                    let next_day = date
+                        .checked_add_signed(Duration::days(1))
+                        .map(|d| d.and_hms(0, 0, 0));
+                    // a date covers the whole day, the last date of all has no day after it
+                    match (comparison, next_day) {
+                        (Comparison::Eq, Some(next_day)) => {
+                            w.ge("created_at", day).lt("created_at", next_day)
+                        }
+                        (Comparison::Eq | Comparison::Ge, _) => w.ge("created_at", day),
+                        (Comparison::Gt, Some(next_day)) => w.ge("created_at", next_day),
+                        (Comparison::Gt, None) => w.and().push_sql("false"),
+                        (Comparison::Lt, _) => w.lt("created_at", day),
+                        (Comparison::Le, Some(next_day)) => w.lt("created_at", next_day),
+                        (Comparison::Le, None) => w,
+                    }
+                }
+            };
+        }
+        w
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn terms(input: &str) -> Vec<Term> {
+        parse(input).unwrap().terms
+    }
+
+    fn error(input: &str) -> ParseError {
+        parse(input).unwrap_err()
+    }
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd(y, m, d)
+    }
+
+    #[test]
+    fn empty_query_has_no_terms() {
+        assert!(terms("").is_empty());
+        assert!(terms("   ").is_empty());
+    }
+
+    #[test]
+    fn bare_and_quoted_words_are_names() {
+        assert_eq!(
+            terms("rex  \"mr whiskers\" leo"),
+            vec![
+                Term::Name("rex".to_string()),
+                Term::Name("mr whiskers".to_string()),
+                Term::Name("leo".to_string()),
+            ]
+        );
+        // an operator without a field name in front is part of the name
+        assert_eq!(terms(":rex"), vec![Term::Name(":rex".to_string())]);
+    }
+
+    #[test]
+    fn fields() {
+        assert_eq!(
+            terms(
+                "name:rex type:Dog vet:carter owner:\"van der berg\" phone:555 age:5 \
+                 created:2020-01-31"
+            ),
+            vec![
+                Term::Name("rex".to_string()),
+                Term::Type(2),
+                Term::Vet(VetRef::Name("carter".to_string())),
+                Term::Owner("van der berg".to_string()),
+                Term::Phone("555".to_string()),
+                Term::Age(Comparison::Eq, 5),
+                Term::Created(Comparison::Eq, date(2020, 1, 31)),
+            ]
+        );
+        // field names are case insensitive
+        assert_eq!(terms("OWNER:falk"), vec![Term::Owner("falk".to_string())]);
+    }
+
+    #[test]
+    fn vet_none_is_unassigned() {
+        assert_eq!(terms("vet:none"), vec![Term::Vet(VetRef::Unassigned)]);
+        assert_eq!(terms("vet:NONE"), vec![Term::Vet(VetRef::Unassigned)]);
+        assert_eq!(
+            terms("vet:\"none of them\""),
+            vec![Term::Vet(VetRef::Name("none of them".to_string()))]
+        );
+    }
+
+    #[test]
+    fn comparison_operators() {
+        assert_eq!(
+            terms("age=1 age>2 age>=3 age<4 age<=5"),
+            vec![
+                Term::Age(Comparison::Eq, 1),
+                Term::Age(Comparison::Gt, 2),
+                Term::Age(Comparison::Ge, 3),
+                Term::Age(Comparison::Lt, 4),
+                Term::Age(Comparison::Le, 5),
+            ]
+        );
+        assert_eq!(
+            terms("created>=2021-03-01 created<2021-04-01"),
+            vec![
+                Term::Created(Comparison::Ge, date(2021, 3, 1)),
+                Term::Created(Comparison::Lt, date(2021, 4, 1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_field() {
+        let e = error("rex colour:red");
+        assert_eq!(e.position, 4);
+        assert!(
+            e.message.starts_with("unknown field `colour`"),
+            "{}",
+            e.message
+        );
+    }
+
+    #[test]
+    fn missing_value() {
+        let e = error("age>");
+        assert_eq!(e.position, 4);
+        assert_eq!(e.message, "missing value after `age>`");
+
+        let e = error("rex owner: falk");
+        assert_eq!(e.position, 10);
+        assert_eq!(e.message, "missing value after `owner:`");
+    }
+
+    #[test]
+    fn unterminated_quote() {
+        let e = error("\"mr whiskers");
+        assert_eq!(e.position, 0);
+        assert_eq!(e.message, "unterminated quote");
+
+        let e = error("type:cat owner:\"van der");
+        assert_eq!(e.position, 15);
+        assert_eq!(e.message, "unterminated quote");
+    }
+
+    #[test]
+    fn invalid_values() {
+        let e = error("type:dragon");
+        assert_eq!(e.position, 5);
+        assert!(
+            e.message.starts_with("unknown pet type `dragon`"),
+            "{}",
+            e.message
+        );
+
+        let e = error("age>old");
+        assert_eq!(e.position, 4);
+
+        let e = error("created:31/01/2020");
+        assert_eq!(e.position, 8);
+
+        // only age and created can be compared
+        let e = error("rex owner>falk");
+        assert_eq!(e.position, 4);
+        assert_eq!(e.message, "`owner` only supports `:`, use `owner:falk`");
+    }
+
+    #[test]
+    fn positions_count_characters() {
+        let e = error("éàü colour:red");
+        assert_eq!(e.position, 4);
+        assert_eq!(e.to_string(), format!("{} (at character 5)", e.message));
+    }
+}
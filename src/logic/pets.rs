@@ -1,10 +1,42 @@
 use std::collections::HashMap;
 
-use chrono::naive::NaiveDateTime;
-use rbatis::{crud::CRUD, crud_table, rbatis::Rbatis};
+use chrono::{
+    naive::{NaiveDate, NaiveDateTime},
+    Duration, Utc,
+};
+use rbatis::{
+    crud::{CRUDMut, CRUD},
+    crud_table,
+    executor::ExecutorMut,
+    plugin::page::Page,
+    rbatis::Rbatis,
+    wrapper::Wrapper,
+};
+use rbson::Bson;
 use serde::Serialize;
+use strsim::jaro_winkler;
+use utoipa::ToSchema;
 
-#[derive(Clone, Serialize)]
+use super::{
+    clinics::Scope,
+    paging::Paging,
+    pet_query::{self, ParseError, PetQuery},
+    vets::{self, Vet},
+};
+
+/// Minimum similarity between two pet names to consider them the same animal
+const NAME_SIMILARITY: f64 = 0.85;
+/// Minimum similarity between two owner names when the phones don't match
+const OWNER_SIMILARITY: f64 = 0.80;
+/// Phone numbers must have between this many digits and `MAX_PHONE_DIGITS`
+pub const MIN_PHONE_DIGITS: usize = 6;
+pub const MAX_PHONE_DIGITS: usize = 15;
+/// Largest age the pet table can hold
+pub const MAX_AGE: u32 = 255;
+/// Longest pet, owner and vet names the tables can hold, in characters
+pub const MAX_NAME_CHARS: usize = 100;
+
+#[derive(Clone, Debug, Serialize)]
 pub enum PetType {
     Cat,
     Dog,
@@ -21,8 +53,33 @@ pub fn types() -> HashMap<u32, PetType> {
     ])
 }
 
+/// Label of a pet type id, as shown in the lists
+pub fn type_label(id: u32) -> String {
+    types()
+        .get(&id)
+        .map(|t| format!("{:?}", t))
+        .unwrap_or_default()
+}
+
+/// Looks up a pet type id by its label, ignoring case
+pub fn type_by_name(name: &str) -> Option<u32> {
+    types()
+        .into_iter()
+        .find(|(_, t)| format!("{:?}", t).eq_ignore_ascii_case(name))
+        .map(|(id, _)| id)
+}
+
+pub fn type_names() -> Vec<String> {
+    let mut types: Vec<(u32, PetType)> = types().into_iter().collect();
+    types.sort_by_key(|(id, _)| *id);
+    types
+        .iter()
+        .map(|(_, t)| format!("{:?}", t).to_lowercase())
+        .collect()
+}
+
 #[crud_table]
-#[derive(Default)]
+#[derive(Default, Clone, ToSchema)]
 pub struct Pet {
     pub id: u32,
     pub name: String,
@@ -33,6 +90,20 @@ pub struct Pet {
     pub vet_id: Option<u32>,
     pub created_at: NaiveDateTime,
     pub created_by: u32,
+    pub clinic_id: u32,
+    /// archived pets are left out of the list unless asked for
+    #[serde(default)]
+    pub archived: bool,
+    /// last change, none for the pets that were never changed through the application
+    #[serde(default)]
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+impl Pet {
+    /// When the record last changed, for the `Last-Modified` header
+    pub fn last_modified(&self) -> NaiveDateTime {
+        self.updated_at.unwrap_or(self.created_at)
+    }
 }
 
 pub async fn delete(rb: &Rbatis, pet: &Pet) -> Result<(), rbatis::Error> {
@@ -41,29 +112,408 @@ pub async fn delete(rb: &Rbatis, pet: &Pet) -> Result<(), rbatis::Error> {
     Ok(())
 }
 
-pub async fn search(rb: &Rbatis, name: Option<&String>) -> Result<Vec<Pet>, rbatis::Error> {
+/// Columns the pet list can be sorted by, the first one is the default
+pub const SORTABLE: &[&str] = &["name", "pet_type", "age", "owner_name", "created_at"];
+
+/// Criteria of the pet list, all of them optional and combined with AND
+#[derive(Serialize, Default, Clone, Debug)]
+pub struct PetFilter {
+    pub name: Option<String>,
+    pub pet_type: Option<u32>,
+    /// 0 selects the pets without a vet
+    pub vet_id: Option<u32>,
+    pub owner_name: Option<String>,
+    pub owner_phone: Option<String>,
+    pub min_age: Option<u32>,
+    pub max_age: Option<u32>,
+    pub created_from: Option<NaiveDate>,
+    pub created_to: Option<NaiveDate>,
+    /// search box text, see `pet_query`
+    pub q: Option<String>,
+    /// lists the archived pets instead of the current ones
+    pub archived: bool,
+    #[serde(skip)]
+    pub query: PetQuery,
+    pub query_error: Option<ParseError>,
+}
+
+impl PetFilter {
+    /// Builds the filter from the list query string, ignoring empty or malformed values
+    pub fn from_query(params: &HashMap<String, String>) -> PetFilter {
+        let text = |key: &str| {
+            params
+                .get(key)
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+        };
+        let number = |key: &str| text(key).and_then(|v| v.parse().ok());
+        let date =
+            |key: &str| text(key).and_then(|v| NaiveDate::parse_from_str(&v, "%Y-%m-%d").ok());
+
+        PetFilter {
+            name: text("name"),
+            pet_type: number("pet_type"),
+            vet_id: number("vet_id"),
+            owner_name: text("owner_name"),
+            owner_phone: text("owner_phone"),
+            min_age: number("min_age"),
+            max_age: number("max_age"),
+            created_from: date("created_from"),
+            created_to: date("created_to"),
+            q: None,
+            archived: params.get("archived").is_some_and(|v| v == "true"),
+            query: PetQuery::default(),
+            query_error: None,
+        }
+        .with_query(text("q"))
+    }
+
+    fn with_query(mut self, q: Option<String>) -> PetFilter {
+        if let Some(q) = &q {
+            match pet_query::parse(q) {
+                Ok(query) => self.query = query,
+                Err(e) => self.query_error = Some(e),
+            }
+        }
+        self.q = q;
+        self
+    }
+
+    pub fn apply(&self, w: Wrapper) -> Wrapper {
+        let mut w = self.query.apply(w).eq("archived", self.archived);
+        if let Some(name) = &self.name {
+            w = w.like("name", name);
+        }
+        if let Some(pet_type) = self.pet_type {
+            w = w.eq("pet_type", pet_type);
+        }
+        match self.vet_id {
+            Some(0) => w = w.is_null("vet_id"),
+            Some(vet_id) => w = w.eq("vet_id", vet_id),
+            None => {}
+        }
+        if let Some(owner_name) = &self.owner_name {
+            w = w.like("owner_name", owner_name);
+        }
+        if let Some(owner_phone) = &self.owner_phone {
+            w = w.like("owner_phone", owner_phone);
+        }
+        if let Some(min_age) = self.min_age {
+            w = w.ge("age", min_age);
+        }
+        if let Some(max_age) = self.max_age {
+            w = w.le("age", max_age);
+        }
+        if let Some(from) = self.created_from {
+            w = w.ge("created_at", from.and_hms(0, 0, 0));
+        }
+        // the whole last day is included, the last date of all has no day after it
+        if let Some(next_day) = self
+            .created_to
+            .and_then(|to| to.checked_add_signed(Duration::days(1)))
+        {
+            w = w.lt("created_at", next_day.and_hms(0, 0, 0));
+        }
+        w
+    }
+}
+
+pub async fn search(
+    rb: &Rbatis,
+    scope: &Scope,
+    filter: &PetFilter,
+    paging: &Paging,
+) -> Result<Page<Pet>, rbatis::Error> {
+    let w = filter.apply(scope.filter(rb.new_wrapper()));
+
+    let pet_page: Page<Pet> = rb
+        .fetch_page_by_wrapper(paging.order(w), &paging.request())
+        .await?;
+
+    Ok(pet_page)
+}
+
+/// Keeps only the digits of a phone number, so "+34 600-11 22" and "3460011 22" compare equal
+pub fn normalize_phone(phone: &str) -> String {
+    phone.chars().filter(|c| c.is_ascii_digit()).collect()
+}
+
+/// Digits with the usual separators, e.g. "+34 (600) 11-22-33"
+pub fn is_valid_phone(phone: &str) -> bool {
+    let digits = normalize_phone(phone).len();
+
+    phone
+        .chars()
+        .all(|c| c.is_ascii_digit() || " +-().".contains(c))
+        && (MIN_PHONE_DIGITS..=MAX_PHONE_DIGITS).contains(&digits)
+}
+
+fn normalize_name(name: &str) -> String {
+    name.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Two records are considered the same animal when the pet names are similar
+/// and the owner is either reachable at the same phone or has a similar name.
+pub fn is_possible_duplicate(a: &Pet, b: &Pet) -> bool {
+    if a.pet_type != b.pet_type {
+        return false;
+    }
+    if jaro_winkler(&normalize_name(&a.name), &normalize_name(&b.name)) < NAME_SIMILARITY {
+        return false;
+    }
+
+    let phone_a = normalize_phone(&a.owner_phone);
+    let same_phone = !phone_a.is_empty() && phone_a == normalize_phone(&b.owner_phone);
+
+    same_phone
+        || jaro_winkler(
+            &normalize_name(&a.owner_name),
+            &normalize_name(&b.owner_name),
+        ) >= OWNER_SIMILARITY
+}
+
+pub async fn find_duplicates(rb: &Rbatis, pet: &Pet) -> Result<Vec<Pet>, rbatis::Error> {
     let w = rb
         .new_wrapper()
-        .like("name", name.unwrap_or(&String::new()));
+        .eq("clinic_id", pet.clinic_id)
+        .eq("pet_type", pet.pet_type)
+        .ne("id", pet.id);
+
+    let candidates: Vec<Pet> = rb.fetch_list_by_wrapper(w).await?;
 
-    let pet_list: Vec<Pet> = rb.fetch_list_by_wrapper(w).await?;
+    Ok(candidates
+        .into_iter()
+        .filter(|c| is_possible_duplicate(pet, c))
+        .collect())
+}
 
-    Ok(pet_list)
+/// Folds `duplicate` into `keep`: blank fields of `keep` are filled from `duplicate`,
+/// its visits and ownership history are moved over and the duplicate record is removed.
+pub async fn merge(rb: &Rbatis, keep: &Pet, duplicate: &Pet) -> Result<(), rbatis::Error> {
+    let mut merged = keep.clone();
+    if merged.owner_name.trim().is_empty() {
+        merged.owner_name = duplicate.owner_name.clone();
+    }
+    if merged.owner_phone.trim().is_empty() {
+        merged.owner_phone = duplicate.owner_phone.clone();
+    }
+    if merged.vet_id.is_none() {
+        merged.vet_id = duplicate.vet_id;
+    }
+    if merged.age == 0 {
+        merged.age = duplicate.age;
+    }
+    if duplicate.created_at < merged.created_at {
+        merged.created_at = duplicate.created_at;
+    }
+    merged.updated_at = Some(Utc::now().naive_utc());
+
+    let mut tx = rb.acquire_begin().await?;
+
+    let result = async {
+        tx.exec(
+            "update visit set pet_id = ? where pet_id = ?",
+            vec![Bson::from(keep.id), Bson::from(duplicate.id)],
+        )
+        .await?;
+        tx.exec(
+            "update ownership_transfer set pet_id = ? where pet_id = ?",
+            vec![Bson::from(keep.id), Bson::from(duplicate.id)],
+        )
+        .await?;
+        let w = rb.new_wrapper().eq("id", keep.id);
+        tx.update_by_wrapper(&merged, w, &[]).await?;
+        tx.remove_by_column::<Pet, _>("id", &duplicate.id).await
+    }
+    .await;
+
+    match result {
+        Ok(_) => tx.commit().await,
+        Err(e) => {
+            tx.rollback().await?;
+            Err(e)
+        }
+    }
 }
 
-pub async fn get(rb: &Rbatis, id: u32) -> Result<Option<Pet>, rbatis::Error> {
-    let c = rb.fetch_by_column("id", id).await?;
+pub async fn get(rb: &Rbatis, scope: &Scope, id: u32) -> Result<Option<Pet>, rbatis::Error> {
+    let w = scope.filter(rb.new_wrapper()).eq("id", id);
+    let c = rb.fetch_by_wrapper(w).await?;
 
     Ok(c)
 }
 
-pub async fn save(rb: &Rbatis, pet: &Pet) -> Result<(), rbatis::Error> {
+/// Pets of the scope among `ids`, for batched lookups
+pub async fn by_ids(rb: &Rbatis, scope: &Scope, ids: &[u32]) -> Result<Vec<Pet>, rbatis::Error> {
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let w = scope.filter(rb.new_wrapper()).r#in("id", ids);
+
+    rb.fetch_list_by_wrapper(w).await
+}
+
+/// The first `per_vet` pets by name of each of `vet_ids`, among the pets of the scope
+pub async fn of_vets(
+    rb: &Rbatis,
+    scope: &Scope,
+    vet_ids: &[u32],
+    per_vet: usize,
+) -> Result<Vec<Pet>, rbatis::Error> {
+    if vet_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let placeholders = vec!["?"; vet_ids.len()].join(", ");
+    let mut args: Vec<Bson> = vet_ids.iter().map(|id| Bson::from(*id)).collect();
+    let clause = scope.sql("p.clinic_id", &mut args);
+    args.push(Bson::from(per_vet as u32));
+
+    // `n` is left over in the rows, `Pet` ignores it
+    rb.fetch(
+        &format!(
+            "select * from (\
+             select p.*, row_number() over \
+             (partition by p.vet_id order by p.name, p.id) as n \
+             from pet p where p.vet_id in ({}){}) ranked \
+             where n <= ? order by name, id",
+            placeholders, clause
+        ),
+        args,
+    )
+    .await
+}
+
+/// Inserts new pets (id 0) or updates existing ones, returns the id
+pub async fn save(rb: &Rbatis, pet: &Pet) -> Result<u32, rbatis::Error> {
+    let pet = Pet {
+        updated_at: Some(Utc::now().naive_utc()),
+        ..pet.clone()
+    };
     if pet.id == 0 {
-        let _result = rb.save(&pet, &[]).await?;
-    } else {
-        let w = rb.new_wrapper().eq("id", pet.id);
-        let _result = rb.update_by_wrapper(&pet, w, &[]).await?;
+        let result = rb.save(&pet, &[]).await?;
+        return Ok(result.last_insert_id.unwrap_or_default() as u32);
     }
+    let w = rb.new_wrapper().eq("id", pet.id);
+    let _result = rb.update_by_wrapper(&pet, w, &[]).await?;
 
-    Ok(())
+    Ok(pet.id)
+}
+
+/// Change made to every pet of a bulk selection on the list
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BulkAction {
+    /// None leaves the pets without a vet
+    AssignVet(Option<u32>),
+    ChangeType(u32),
+    Archive,
+    Restore,
+}
+
+/// Selected pet that was left unchanged, and why
+#[derive(Serialize, Debug)]
+pub struct BulkFailure {
+    pub id: u32,
+    pub name: String,
+    pub reason: String,
+}
+
+#[derive(Serialize, Default)]
+pub struct BulkResult {
+    pub updated: Vec<Pet>,
+    pub failed: Vec<BulkFailure>,
+}
+
+impl BulkAction {
+    /// Changes `pet`, or tells why the action does not apply to it
+    fn apply(&self, pet: &mut Pet, vet: Option<&Vet>) -> Result<(), &'static str> {
+        match *self {
+            BulkAction::AssignVet(None) => pet.vet_id = None,
+            BulkAction::AssignVet(Some(_)) => match vet {
+                Some(vet) if vet.active && vet.clinic_id == pet.clinic_id => {
+                    pet.vet_id = Some(vet.id)
+                }
+                _ => return Err("the vet does not work at the pet's clinic"),
+            },
+            BulkAction::ChangeType(pet_type) => {
+                if !types().contains_key(&pet_type) {
+                    return Err("unknown pet type");
+                }
+                pet.pet_type = pet_type;
+            }
+            BulkAction::Archive if pet.archived => return Err("already archived"),
+            BulkAction::Archive => pet.archived = true,
+            BulkAction::Restore if !pet.archived => return Err("not archived"),
+            BulkAction::Restore => pet.archived = false,
+        }
+        Ok(())
+    }
+}
+
+/// Applies `action` to the pets of the scope among `ids`. The pets it can't apply to are
+/// reported in the result, the others are all saved in one transaction, or none of them.
+pub async fn bulk_update(
+    rb: &Rbatis,
+    scope: &Scope,
+    ids: &[u32],
+    action: BulkAction,
+) -> Result<BulkResult, rbatis::Error> {
+    let selected = by_ids(rb, scope, ids).await?;
+    let vet = match action {
+        BulkAction::AssignVet(Some(vet_id)) => vets::by_ids(rb, scope, &[vet_id]).await?.pop(),
+        _ => None,
+    };
+
+    let now = Utc::now().naive_utc();
+    let mut result = BulkResult::default();
+    for &id in ids {
+        match selected.iter().find(|p| p.id == id) {
+            Some(pet) => {
+                let mut pet = Pet {
+                    updated_at: Some(now),
+                    ..pet.clone()
+                };
+                match action.apply(&mut pet, vet.as_ref()) {
+                    Ok(()) => result.updated.push(pet),
+                    Err(reason) => result.failed.push(BulkFailure {
+                        id,
+                        name: pet.name,
+                        reason: reason.to_string(),
+                    }),
+                }
+            }
+            None => result.failed.push(BulkFailure {
+                id,
+                name: String::new(),
+                reason: "not found".to_string(),
+            }),
+        }
+    }
+    if result.updated.is_empty() {
+        return Ok(result);
+    }
+
+    let mut tx = rb.acquire_begin().await?;
+
+    let saved = async {
+        for pet in &result.updated {
+            let w = rb.new_wrapper().eq("id", pet.id);
+            tx.update_by_wrapper(pet, w, &[]).await?;
+        }
+        Ok::<(), rbatis::Error>(())
+    }
+    .await;
+
+    match saved {
+        Ok(_) => {
+            tx.commit().await?;
+            Ok(result)
+        }
+        Err(e) => {
+            tx.rollback().await?;
+            Err(e)
+        }
+    }
 }
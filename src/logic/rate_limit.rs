@@ -0,0 +1,106 @@
+//! Request counters of the rate limits, in fixed windows. They are kept in Redis so every
+//! instance shares them, and in memory while Redis can't be reached.
+
+use petclinic::RateLimit;
+use redis::{Connection, RedisResult};
+
+use crate::Context;
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Prefix of the Redis keys of the counters
+const KEY_PREFIX: &str = "petclinic:rate";
+/// The memory counters of past windows are dropped once there are this many
+const MAX_MEMORY_COUNTERS: usize = 10_000;
+
+/// Routes sharing a limit, see `Env`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Group {
+    Login,
+    Api,
+}
+
+impl Group {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Group::Login => "login",
+            Group::Api => "api",
+        }
+    }
+
+    pub fn limit(&self, state: &Context) -> RateLimit {
+        match self {
+            Group::Login => state.env.login_rate_limit,
+            Group::Api => state.env.api_rate_limit,
+        }
+    }
+}
+
+/// Counters of this instance, used when Redis fails
+#[derive(Default)]
+pub struct MemoryCounters {
+    /// window and count by key
+    counters: Mutex<HashMap<String, (u64, u32)>>,
+}
+
+impl MemoryCounters {
+    fn increment(&self, key: &str, window: u64) -> u32 {
+        let mut counters = self.counters.lock().unwrap();
+        if counters.len() >= MAX_MEMORY_COUNTERS {
+            counters.retain(|_, (w, _)| *w == window);
+        }
+        let counter = counters.entry(key.to_string()).or_insert((window, 0));
+        if counter.0 != window {
+            *counter = (window, 0);
+        }
+        counter.1 += 1;
+
+        counter.1
+    }
+}
+
+fn redis_increment(connection: &mut Connection, key: &str, seconds: u64) -> RedisResult<u32> {
+    let (count,): (u32,) = redis::pipe()
+        .atomic()
+        .incr(key, 1)
+        .expire(key, seconds as usize)
+        .ignore()
+        .query(connection)?;
+
+    Ok(count)
+}
+
+/// Counts a request of `client` (e.g. `ip:127.0.0.1`) in `group`. Over the limit, returns the
+/// seconds left until the window ends, for `Retry-After`.
+pub fn hit(state: &Context, group: Group, client: &str) -> Option<u64> {
+    let limit = group.limit(state);
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let window = now / limit.window;
+    let key = format!("{}:{}:{}", KEY_PREFIX, group.as_str(), client);
+
+    let windowed_key = format!("{}:{}", key, window);
+    let count = {
+        let mut connection = state.redis_connection.lock().unwrap();
+        redis_increment(&mut connection, &windowed_key, limit.window)
+    };
+    let count = match count {
+        Ok(count) => count,
+        Err(e) => {
+            tracing::warn!("Rate limit counted in memory, Redis failed: {}", e);
+            state.rate_limits.increment(&key, window)
+        }
+    };
+
+    if count > limit.requests {
+        Some((window + 1) * limit.window - now)
+    } else {
+        None
+    }
+}
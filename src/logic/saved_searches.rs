@@ -0,0 +1,154 @@
+use chrono::{naive::NaiveDateTime, Utc};
+use rbatis::{
+    crud::{CRUDMut, CRUD},
+    crud_table,
+    executor::ExecutorMut,
+    rbatis::Rbatis,
+};
+use rbson::Bson;
+
+use super::users::User;
+
+/// Named pet list filters and sort order of a user, stored as the list query string
+#[crud_table]
+#[derive(Default, Clone)]
+pub struct SavedSearch {
+    pub id: u32,
+    pub user_id: u32,
+    pub name: String,
+    pub query: String,
+    /// shown instead of the unfiltered list when opening the pet list
+    pub is_default: bool,
+    pub created_at: NaiveDateTime,
+}
+
+/// `query` decoded and encoded again, safe to put in a URL. None when it can't be decoded.
+pub fn encode_query(query: &str) -> Option<String> {
+    let pairs: Vec<(String, String)> = serde_urlencoded::from_str(query).ok()?;
+
+    serde_urlencoded::to_string(pairs).ok()
+}
+
+/// Removes the page number, a saved search always starts on the first page. None when `query`
+/// can't be decoded.
+pub fn clean_query(query: &str) -> Option<String> {
+    let pairs: Vec<(String, String)> = serde_urlencoded::from_str(query).ok()?;
+    let pairs: Vec<_> = pairs
+        .into_iter()
+        .filter(|(key, _)| key != "page" && key != "view")
+        .collect();
+
+    serde_urlencoded::to_string(pairs).ok()
+}
+
+pub async fn of_user(rb: &Rbatis, user: &User) -> Result<Vec<SavedSearch>, rbatis::Error> {
+    let w = rb
+        .new_wrapper()
+        .eq("user_id", user.id)
+        .order_by(true, &["name"]);
+
+    let searches: Vec<SavedSearch> = rb.fetch_list_by_wrapper(w).await?;
+
+    Ok(searches)
+}
+
+pub async fn get(rb: &Rbatis, user: &User, id: u32) -> Result<Option<SavedSearch>, rbatis::Error> {
+    let w = rb.new_wrapper().eq("user_id", user.id).eq("id", id);
+    let s = rb.fetch_by_wrapper(w).await?;
+
+    Ok(s)
+}
+
+pub async fn default_of(rb: &Rbatis, user: &User) -> Result<Option<SavedSearch>, rbatis::Error> {
+    let w = rb
+        .new_wrapper()
+        .eq("user_id", user.id)
+        .eq("is_default", true)
+        .limit(1);
+    let s = rb.fetch_by_wrapper(w).await?;
+
+    Ok(s)
+}
+
+/// Saves a search of `query`, as returned by `clean_query`
+pub async fn save(
+    rb: &Rbatis,
+    user: &User,
+    name: &str,
+    query: &str,
+    is_default: bool,
+) -> Result<(), rbatis::Error> {
+    let search = SavedSearch {
+        id: 0,
+        user_id: user.id,
+        name: name.to_string(),
+        query: query.to_string(),
+        is_default,
+        created_at: Utc::now().naive_utc(),
+    };
+
+    let mut tx = rb.acquire_begin().await?;
+
+    let result = async {
+        if is_default {
+            clear_default(&mut tx, user).await?;
+        }
+        tx.save(&search, &[]).await
+    }
+    .await;
+
+    match result {
+        Ok(_) => tx.commit().await,
+        Err(e) => {
+            tx.rollback().await?;
+            Err(e)
+        }
+    }
+}
+
+async fn clear_default(tx: &mut impl ExecutorMut, user: &User) -> Result<(), rbatis::Error> {
+    tx.exec(
+        "update saved_search set is_default = false where user_id = ?",
+        vec![Bson::from(user.id)],
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Makes `search` the user's default view, or removes it as default if it already was
+pub async fn toggle_default(
+    rb: &Rbatis,
+    user: &User,
+    search: &SavedSearch,
+) -> Result<(), rbatis::Error> {
+    let mut tx = rb.acquire_begin().await?;
+
+    let result = async {
+        clear_default(&mut tx, user).await?;
+        if !search.is_default {
+            tx.exec(
+                "update saved_search set is_default = true where id = ? and user_id = ?",
+                vec![Bson::from(search.id), Bson::from(user.id)],
+            )
+            .await?;
+        }
+        Ok(())
+    }
+    .await;
+
+    match result {
+        Ok(_) => tx.commit().await,
+        Err(e) => {
+            tx.rollback().await?;
+            Err(e)
+        }
+    }
+}
+
+pub async fn delete(rb: &Rbatis, search: &SavedSearch) -> Result<(), rbatis::Error> {
+    rb.remove_by_column::<SavedSearch, _>("id", &search.id)
+        .await?;
+
+    Ok(())
+}
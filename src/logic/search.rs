@@ -0,0 +1,280 @@
+use rbatis::rbatis::Rbatis;
+use rbson::Bson;
+use serde::{Deserialize, Serialize};
+
+use super::clinics::Scope;
+
+/// Maximum number of hits returned per group
+const GROUP_LIMIT: usize = 20;
+/// Characters of context kept before the first match in a snippet
+const SNIPPET_CONTEXT: usize = 40;
+const SNIPPET_LENGTH: usize = 160;
+
+/// Part of a snippet, `highlight` is set on the pieces matching a search term
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Fragment {
+    pub text: String,
+    pub highlight: bool,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Hit {
+    pub url: String,
+    pub title: Vec<Fragment>,
+    pub snippet: Vec<Fragment>,
+    pub score: f64,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct Results {
+    pub pets: Vec<Hit>,
+    pub owners: Vec<Hit>,
+    pub vets: Vec<Hit>,
+    pub visits: Vec<Hit>,
+}
+
+impl Results {
+    /// Non empty groups with their label, in display order
+    pub fn groups(&self) -> Vec<(&'static str, &Vec<Hit>)> {
+        [
+            ("Pets", &self.pets),
+            ("Owners", &self.owners),
+            ("Veterinarians", &self.vets),
+            ("Visit notes", &self.visits),
+        ]
+        .into_iter()
+        .filter(|(_, hits)| !hits.is_empty())
+        .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pets.is_empty()
+            && self.owners.is_empty()
+            && self.vets.is_empty()
+            && self.visits.is_empty()
+    }
+}
+
+#[derive(Deserialize)]
+struct PetRow {
+    id: u32,
+    name: Option<String>,
+    owner_name: Option<String>,
+    score: f64,
+}
+
+#[derive(Deserialize)]
+struct OwnerRow {
+    owner_name: Option<String>,
+    owner_phone: Option<String>,
+    pets: u64,
+    score: f64,
+}
+
+#[derive(Deserialize)]
+struct VetRow {
+    id: u32,
+    name: Option<String>,
+    score: f64,
+}
+
+#[derive(Deserialize)]
+struct VisitRow {
+    pet_id: u32,
+    pet_name: Option<String>,
+    notes: Option<String>,
+    score: f64,
+}
+
+/// Splits the user input into the words searched for, dropping anything
+/// that has a meaning in the MySQL boolean full-text syntax
+pub fn terms(query: &str) -> Vec<String> {
+    query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .map(|t| t.to_lowercase())
+        .collect()
+}
+
+/// Boolean mode expression ranking rows matching any of the terms, as prefixes
+fn against(terms: &[String]) -> String {
+    terms
+        .iter()
+        .map(|t| format!("{}*", t))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Cuts a window of `text` around the first match and marks every match in it
+pub fn highlight(text: &str, terms: &[String]) -> Vec<Fragment> {
+    let chars: Vec<char> = text.chars().collect();
+    let lower: Vec<char> = chars
+        .iter()
+        .map(|c| c.to_lowercase().next().unwrap_or(*c))
+        .collect();
+
+    let mut marked = vec![false; chars.len()];
+    for term in terms {
+        let term: Vec<char> = term.chars().collect();
+        if term.is_empty() {
+            continue;
+        }
+        let mut i = 0;
+        while i + term.len() <= lower.len() {
+            // only match at the start of a word, like the full-text index does
+            let word_start = i == 0 || !lower[i - 1].is_alphanumeric();
+            if word_start && lower[i..i + term.len()] == term[..] {
+                marked[i..i + term.len()].fill(true);
+                i += term.len();
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    let first = marked.iter().position(|m| *m).unwrap_or(0);
+    let start = first.saturating_sub(SNIPPET_CONTEXT);
+    let end = (start + SNIPPET_LENGTH).min(chars.len());
+
+    let mut fragments: Vec<Fragment> = Vec::new();
+    if start > 0 {
+        fragments.push(Fragment {
+            text: "…".to_string(),
+            highlight: false,
+        });
+    }
+    for i in start..end {
+        match fragments.last_mut() {
+            Some(f) if f.highlight == marked[i] && f.text != "…" => f.text.push(chars[i]),
+            _ => fragments.push(Fragment {
+                text: chars[i].to_string(),
+                highlight: marked[i],
+            }),
+        }
+    }
+    if end < chars.len() {
+        fragments.push(Fragment {
+            text: "…".to_string(),
+            highlight: false,
+        });
+    }
+    fragments
+}
+
+pub async fn search(rb: &Rbatis, scope: &Scope, query: &str) -> Result<Results, rbatis::Error> {
+    let terms = terms(query);
+    if terms.is_empty() {
+        return Ok(Results::default());
+    }
+    let against = against(&terms);
+
+    let mut args = vec![Bson::from(against.clone()), Bson::from(against.clone())];
+    let clause = scope.sql("clinic_id", &mut args);
+    let pets: Vec<PetRow> = rb
+        .fetch(
+            &format!(
+                "select id, name, owner_name, \
+                 match(name, owner_name, owner_phone) against (? in boolean mode) as score \
+                 from pet where match(name, owner_name, owner_phone) against (? in boolean mode){} \
+                 order by score desc limit {}",
+                clause, GROUP_LIMIT
+            ),
+            args,
+        )
+        .await?;
+
+    let mut args = vec![Bson::from(against.clone()), Bson::from(against.clone())];
+    let clause = scope.sql("clinic_id", &mut args);
+    let owners: Vec<OwnerRow> = rb
+        .fetch(
+            &format!(
+                "select owner_name, owner_phone, count(*) as pets, \
+                 max(match(name, owner_name, owner_phone) against (? in boolean mode)) as score \
+                 from pet where match(name, owner_name, owner_phone) against (? in boolean mode){} \
+                 group by owner_name, owner_phone order by score desc limit {}",
+                clause, GROUP_LIMIT
+            ),
+            args,
+        )
+        .await?;
+
+    let mut args = vec![Bson::from(against.clone()), Bson::from(against.clone())];
+    let clause = scope.sql("clinic_id", &mut args);
+    let vets: Vec<VetRow> = rb
+        .fetch(
+            &format!(
+                "select id, name, match(name) against (? in boolean mode) as score \
+                 from vet where match(name) against (? in boolean mode) and active = true{} \
+                 order by score desc limit {}",
+                clause, GROUP_LIMIT
+            ),
+            args,
+        )
+        .await?;
+
+    let mut args = vec![Bson::from(against.clone()), Bson::from(against)];
+    let clause = scope.sql("p.clinic_id", &mut args);
+    let visits: Vec<VisitRow> = rb
+        .fetch(
+            &format!(
+                "select v.pet_id, p.name as pet_name, v.notes, \
+                 match(v.notes) against (? in boolean mode) as score \
+                 from visit v join pet p on p.id = v.pet_id \
+                 where match(v.notes) against (? in boolean mode){} \
+                 order by score desc limit {}",
+                clause, GROUP_LIMIT
+            ),
+            args,
+        )
+        .await?;
+
+    Ok(Results {
+        pets: pets
+            .into_iter()
+            .map(|p| Hit {
+                url: format!("/pets/{}", p.id),
+                title: highlight(&p.name.unwrap_or_default(), &terms),
+                snippet: highlight(&p.owner_name.unwrap_or_default(), &terms),
+                score: p.score,
+            })
+            .collect(),
+        owners: owners
+            .into_iter()
+            .map(|o| {
+                let name = o.owner_name.unwrap_or_default();
+                let phone = o.owner_phone.unwrap_or_default();
+                Hit {
+                    url: format!(
+                        "/pets?{}",
+                        serde_urlencoded::to_string([
+                            ("owner_name", &name),
+                            ("owner_phone", &phone)
+                        ])
+                        .unwrap_or_default()
+                    ),
+                    title: highlight(&name, &terms),
+                    snippet: highlight(&format!("{} · {} pet(s)", phone, o.pets), &terms),
+                    score: o.score,
+                }
+            })
+            .collect(),
+        vets: vets
+            .into_iter()
+            .map(|v| Hit {
+                url: format!("/vets/{}", v.id),
+                title: highlight(&v.name.unwrap_or_default(), &terms),
+                snippet: Vec::new(),
+                score: v.score,
+            })
+            .collect(),
+        visits: visits
+            .into_iter()
+            .map(|v| Hit {
+                url: format!("/pets/{}", v.pet_id),
+                title: highlight(&v.pet_name.unwrap_or_default(), &terms),
+                snippet: highlight(&v.notes.unwrap_or_default(), &terms),
+                score: v.score,
+            })
+            .collect(),
+    })
+}
@@ -0,0 +1,502 @@
+//! Import of the data of a Spring Petclinic instance from a SQL dump.
+//!
+//! Spring keeps owners in their own table while here they are fields of the pet,
+//! and neither specialties nor addresses have a place in our tables; everything
+//! left out is listed in `Plan::unmapped`.
+
+use std::collections::HashMap;
+
+use chrono::{naive::NaiveDate, Datelike, Utc};
+use rbatis::{crud::CRUDMut, rbatis::Rbatis};
+
+use super::{
+    pets::{self, Pet},
+    sql_dump::{Dump, Row},
+    vets::Vet,
+    visits::Visit,
+};
+
+/// Columns of the Spring tables, in the order of its `schema.sql`
+const SPRING_TABLES: &[(&str, &[&str])] = &[
+    ("vets", &["id", "first_name", "last_name"]),
+    ("specialties", &["id", "name"]),
+    ("vet_specialties", &["vet_id", "specialty_id"]),
+    ("types", &["id", "name"]),
+    (
+        "owners",
+        &[
+            "id",
+            "first_name",
+            "last_name",
+            "address",
+            "city",
+            "telephone",
+        ],
+    ),
+    ("pets", &["id", "name", "birth_date", "type_id", "owner_id"]),
+    ("visits", &["id", "pet_id", "visit_date", "description"]),
+];
+
+/// Columns of a Spring table, for INSERT statements without a column list
+pub fn columns_of(table: &str) -> Option<Vec<String>> {
+    SPRING_TABLES
+        .iter()
+        .find(|(name, _)| *name == table)
+        .map(|(_, columns)| columns.iter().map(|c| c.to_string()).collect())
+}
+
+/// Vet given to the imported visits, Spring visits have none
+#[derive(Clone, Debug, PartialEq)]
+pub enum VisitVet {
+    /// id of a vet of the dump
+    Imported(String),
+    /// id of a vet already in the clinic
+    Existing(u32),
+}
+
+/// What the import will write, ids are still the Spring ones
+#[derive(Default)]
+pub struct Plan {
+    pub vets: Vec<(String, Vet)>,
+    pub pets: Vec<(String, Pet)>,
+    pub visits: Vec<(String, Visit)>,
+    pub visit_vet: Option<VisitVet>,
+    pub unmapped: Vec<String>,
+}
+
+fn text<'a>(row: &'a Row, column: &str) -> &'a str {
+    row.get(column)
+        .and_then(|v| v.as_deref())
+        .unwrap_or("")
+        .trim()
+}
+
+fn full_name(row: &Row) -> String {
+    format!("{} {}", text(row, "first_name"), text(row, "last_name"))
+        .trim()
+        .to_string()
+}
+
+/// Reads `YYYY-MM-DD`, with or without a time
+fn date(value: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(value.get(..10)?, "%Y-%m-%d").ok()
+}
+
+fn age(birth_date: NaiveDate, today: NaiveDate) -> u32 {
+    let mut years = today.year() - birth_date.year();
+    if (today.month(), today.day()) < (birth_date.month(), birth_date.day()) {
+        years -= 1;
+    }
+    years.max(0) as u32
+}
+
+/// Maps the rows of the dump to our records. `visit_vet` names the vet, of the dump or
+/// of `existing_vets` (by lowercase name), given to the visits.
+pub fn plan(
+    dump: &Dump,
+    clinic_id: u32,
+    created_by: u32,
+    visit_vet: Option<&str>,
+    existing_vets: &HashMap<String, u32>,
+) -> Result<Plan, String> {
+    let today = Utc::now().naive_utc().date();
+    let mut plan = Plan::default();
+
+    let mut tables: Vec<(&String, &Vec<Row>)> = dump.tables.iter().collect();
+    tables.sort_by_key(|(table, _)| *table);
+    for (table, rows) in tables {
+        if columns_of(table).is_none() {
+            plan.unmapped.push(format!(
+                "table `{}` is not part of the Spring schema, {} row(s) ignored",
+                table,
+                rows.len()
+            ));
+        }
+    }
+
+    // vets, with their specialties reported as lost
+    let specialties: HashMap<&str, &str> = dump
+        .rows("specialties")
+        .iter()
+        .map(|r| (text(r, "id"), text(r, "name")))
+        .collect();
+    for row in dump.rows("vets") {
+        let name = full_name(row);
+        let vet_specialties: Vec<&str> = dump
+            .rows("vet_specialties")
+            .iter()
+            .filter(|s| text(s, "vet_id") == text(row, "id"))
+            .map(|s| {
+                specialties
+                    .get(text(s, "specialty_id"))
+                    .copied()
+                    .unwrap_or("?")
+            })
+            .collect();
+        if !vet_specialties.is_empty() {
+            plan.unmapped.push(format!(
+                "vet {}: specialties {} are not kept",
+                name,
+                vet_specialties.join(", ")
+            ));
+        }
+        plan.vets.push((
+            text(row, "id").to_string(),
+            Vet {
+                name,
+                active: true,
+                clinic_id,
+                ..Default::default()
+            },
+        ));
+    }
+
+    plan.visit_vet = match visit_vet {
+        None => None,
+        Some(name) => {
+            let lowercase = name.trim().to_lowercase();
+            match plan
+                .vets
+                .iter()
+                .find(|(_, v)| v.name.to_lowercase() == lowercase)
+            {
+                Some((id, _)) => Some(VisitVet::Imported(id.clone())),
+                None => match existing_vets.get(&lowercase) {
+                    Some(id) => Some(VisitVet::Existing(*id)),
+                    None => {
+                        return Err(format!("no vet named `{}` in the dump or the clinic", name))
+                    }
+                },
+            }
+        }
+    };
+
+    // pets, carrying the name and phone of their owner
+    let types: HashMap<&str, &str> = dump
+        .rows("types")
+        .iter()
+        .map(|r| (text(r, "id"), text(r, "name")))
+        .collect();
+    let owners: HashMap<&str, &Row> = dump
+        .rows("owners")
+        .iter()
+        .map(|r| (text(r, "id"), r))
+        .collect();
+    for row in dump.rows("owners") {
+        let has_pets = dump
+            .rows("pets")
+            .iter()
+            .any(|p| text(p, "owner_id") == text(row, "id"));
+        if !has_pets {
+            plan.unmapped.push(format!(
+                "owner {} has no pets and is not imported",
+                full_name(row)
+            ));
+        } else if !text(row, "address").is_empty() || !text(row, "city").is_empty() {
+            plan.unmapped.push(format!(
+                "owner {}: address `{}, {}` is not kept",
+                full_name(row),
+                text(row, "address"),
+                text(row, "city")
+            ));
+        }
+    }
+
+    for row in dump.rows("pets") {
+        let name = text(row, "name");
+        let label = format!("pet {} (id {})", name, text(row, "id"));
+
+        let type_name = types.get(text(row, "type_id")).copied().unwrap_or("");
+        let pet_type = match pets::type_by_name(type_name) {
+            Some(pet_type) => pet_type,
+            None => {
+                plan.unmapped.push(format!(
+                    "{}: type `{}` has no equivalent, not imported",
+                    label, type_name
+                ));
+                continue;
+            }
+        };
+        let owner = match owners.get(text(row, "owner_id")) {
+            Some(owner) => owner,
+            None => {
+                plan.unmapped.push(format!(
+                    "{}: owner {} not found, not imported",
+                    label,
+                    text(row, "owner_id")
+                ));
+                continue;
+            }
+        };
+        let age = match date(text(row, "birth_date")) {
+            Some(birth_date) => age(birth_date, today),
+            None => {
+                plan.unmapped.push(format!(
+                    "{}: birth date `{}` not understood, age left empty",
+                    label,
+                    text(row, "birth_date")
+                ));
+                0
+            }
+        };
+
+        plan.pets.push((
+            text(row, "id").to_string(),
+            Pet {
+                name: name.to_string(),
+                owner_name: full_name(owner),
+                owner_phone: text(owner, "telephone").to_string(),
+                age,
+                pet_type,
+                vet_id: None,
+                created_at: Utc::now().naive_utc(),
+                created_by,
+                clinic_id,
+                ..Default::default()
+            },
+        ));
+    }
+
+    for row in dump.rows("visits") {
+        let label = format!("visit {} ({})", text(row, "id"), text(row, "visit_date"));
+        let pet_id = text(row, "pet_id");
+
+        if plan.visit_vet.is_none() {
+            plan.unmapped.push(format!(
+                "{}: Spring visits have no vet, name one with --visit-vet to import them",
+                label
+            ));
+            continue;
+        }
+        if !plan.pets.iter().any(|(id, _)| id == pet_id) {
+            plan.unmapped
+                .push(format!("{}: pet {} is not imported", label, pet_id));
+            continue;
+        }
+        let visit_date = match date(text(row, "visit_date")) {
+            Some(visit_date) => visit_date.and_hms(0, 0, 0),
+            None => {
+                plan.unmapped
+                    .push(format!("{}: date not understood, not imported", label));
+                continue;
+            }
+        };
+
+        plan.visits.push((
+            pet_id.to_string(),
+            Visit {
+                visit_date,
+                notes: Some(text(row, "description").to_string()).filter(|n| !n.is_empty()),
+                ..Default::default()
+            },
+        ));
+    }
+
+    Ok(plan)
+}
+
+fn inserted_id(id: Option<i64>) -> Result<u32, rbatis::Error> {
+    id.map(|id| id as u32)
+        .ok_or_else(|| rbatis::Error::from("the database did not return the new id"))
+}
+
+/// Writes the plan in a single transaction
+pub async fn import(rb: &Rbatis, plan: &Plan) -> Result<(), rbatis::Error> {
+    let mut tx = rb.acquire_begin().await?;
+
+    let result = async {
+        let mut vet_ids: HashMap<&str, u32> = HashMap::new();
+        for (spring_id, vet) in &plan.vets {
+            let id = inserted_id(tx.save(vet, &[]).await?.last_insert_id)?;
+            vet_ids.insert(spring_id, id);
+        }
+
+        let mut pet_ids: HashMap<&str, u32> = HashMap::new();
+        for (spring_id, pet) in &plan.pets {
+            let id = inserted_id(tx.save(pet, &[]).await?.last_insert_id)?;
+            pet_ids.insert(spring_id, id);
+        }
+
+        let vet_id = match &plan.visit_vet {
+            Some(VisitVet::Imported(spring_id)) => vet_ids.get(spring_id.as_str()).copied(),
+            Some(VisitVet::Existing(id)) => Some(*id),
+            None => None,
+        };
+        for (spring_pet_id, visit) in &plan.visits {
+            if let (Some(pet_id), Some(vet_id)) = (pet_ids.get(spring_pet_id.as_str()), vet_id) {
+                let visit = Visit {
+                    pet_id: *pet_id,
+                    vet_id,
+                    ..visit.clone()
+                };
+                tx.save(&visit, &[]).await?;
+            }
+        }
+        Ok(())
+    }
+    .await;
+
+    match result {
+        Ok(_) => tx.commit().await,
+        Err(e) => {
+            tx.rollback().await?;
+            Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::sql_dump;
+
+    const DUMP: &str = include_str!("../../res/test/spring_dump.sql");
+    const CLINIC: u32 = 7;
+    const USER: u32 = 3;
+
+    fn dump() -> Dump {
+        sql_dump::parse(DUMP, columns_of).unwrap()
+    }
+
+    fn plan_of(visit_vet: Option<&str>, existing_vets: &[(&str, u32)]) -> Result<Plan, String> {
+        let existing_vets = existing_vets
+            .iter()
+            .map(|(name, id)| (name.to_string(), *id))
+            .collect();
+        plan(&dump(), CLINIC, USER, visit_vet, &existing_vets)
+    }
+
+    fn day(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd(y, m, d)
+    }
+
+    fn has(plan: &Plan, message: &str) -> bool {
+        plan.unmapped.iter().any(|m| m == message)
+    }
+
+    #[test]
+    fn vets_lose_their_specialties() {
+        let plan = plan_of(None, &[]).unwrap();
+
+        let vets: Vec<(&str, &str)> = plan
+            .vets
+            .iter()
+            .map(|(id, v)| (id.as_str(), v.name.as_str()))
+            .collect();
+        assert_eq!(vets, vec![("1", "James Carter"), ("2", "Helen Leary")]);
+        assert!(plan
+            .vets
+            .iter()
+            .all(|(_, v)| v.active && v.clinic_id == CLINIC));
+        assert!(has(
+            &plan,
+            "vet Helen Leary: specialties radiology, surgery are not kept"
+        ));
+    }
+
+    #[test]
+    fn pets_carry_their_owner() {
+        let plan = plan_of(None, &[]).unwrap();
+
+        let ids: Vec<&str> = plan.pets.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(ids, vec!["1", "2", "3"]);
+
+        let (_, leo) = &plan.pets[0];
+        assert_eq!(leo.name, "Leo");
+        assert_eq!(leo.owner_name, "George Franklin");
+        assert_eq!(leo.owner_phone, "6085551023");
+        assert_eq!(leo.pet_type, 1);
+        assert_eq!(leo.vet_id, None);
+        assert_eq!((leo.clinic_id, leo.created_by), (CLINIC, USER));
+
+        let (_, basil) = &plan.pets[1];
+        assert_eq!(basil.pet_type, 2);
+        assert_eq!(basil.owner_name, "Betty Davis");
+
+        // the birth date could not be read
+        assert_eq!(plan.pets[2].1.age, 0);
+    }
+
+    #[test]
+    fn reports_what_is_left_out() {
+        let plan = plan_of(Some("james carter"), &[]).unwrap();
+
+        for message in [
+            "table `audit_log` is not part of the Spring schema, 1 row(s) ignored",
+            "owner Eduardo Rodriquez has no pets and is not imported",
+            "owner George Franklin: address `110 W. Liberty St., Madison` is not kept",
+            "pet Rosy (id 3): birth date `unknown` not understood, age left empty",
+            "pet Jewel (id 4): type `snake` has no equivalent, not imported",
+            "pet Iggy (id 5): owner 9 not found, not imported",
+            "visit 2 (2013-01-02): pet 4 is not imported",
+            "visit 4 (soon): date not understood, not imported",
+        ] {
+            assert!(
+                has(&plan, message),
+                "missing `{}` in {:?}",
+                message,
+                plan.unmapped
+            );
+        }
+        // an owner without address has nothing to report
+        assert!(!plan.unmapped.iter().any(|m| m.contains("Betty Davis")));
+    }
+
+    #[test]
+    fn visits_need_a_vet() {
+        let plan = plan_of(None, &[]).unwrap();
+
+        assert!(plan.visits.is_empty());
+        assert_eq!(
+            plan.unmapped
+                .iter()
+                .filter(|m| m.ends_with("name one with --visit-vet to import them"))
+                .count(),
+            4
+        );
+    }
+
+    #[test]
+    fn visits_of_imported_pets() {
+        let plan = plan_of(Some(" James Carter "), &[]).unwrap();
+
+        assert_eq!(plan.visit_vet, Some(VisitVet::Imported("1".to_string())));
+        let visits: Vec<(&str, NaiveDate, Option<&str>)> = plan
+            .visits
+            .iter()
+            .map(|(pet_id, v)| (pet_id.as_str(), v.visit_date.date(), v.notes.as_deref()))
+            .collect();
+        assert_eq!(
+            visits,
+            vec![
+                ("1", day(2013, 1, 1), Some("rabies shot; it's done")),
+                ("2", day(2013, 1, 3), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn visit_vet_of_the_clinic() {
+        let plan = plan_of(Some("Jane Doe"), &[("jane doe", 12)]).unwrap();
+        assert_eq!(plan.visit_vet, Some(VisitVet::Existing(12)));
+
+        let e = plan_of(Some("nobody"), &[("jane doe", 12)]).err();
+        assert_eq!(
+            e.as_deref(),
+            Some("no vet named `nobody` in the dump or the clinic")
+        );
+    }
+
+    #[test]
+    fn dates_and_ages() {
+        assert_eq!(date("2012-08-06"), Some(day(2012, 8, 6)));
+        assert_eq!(date("2012-08-06 10:30:00"), Some(day(2012, 8, 6)));
+        assert_eq!(date("06/08/2012"), None);
+        assert_eq!(date(""), None);
+
+        let birth = day(2010, 9, 7);
+        assert_eq!(age(birth, day(2020, 9, 6)), 9);
+        assert_eq!(age(birth, day(2020, 9, 7)), 10);
+        assert_eq!(age(birth, day(2009, 1, 1)), 0);
+    }
+}
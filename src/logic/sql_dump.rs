@@ -0,0 +1,429 @@
+//! Reads the rows of the `INSERT` statements of a MySQL dump, as written by
+//! `mysqldump` or by hand (`INSERT [IGNORE] INTO t [(cols)] VALUES (..), (..);`).
+//! Every other statement is skipped.
+
+use std::collections::HashMap;
+
+/// A row by column name, `None` for SQL NULL
+pub type Row = HashMap<String, Option<String>>;
+
+/// Rows of every table found in the dump
+#[derive(Default, Debug)]
+pub struct Dump {
+    pub tables: HashMap<String, Vec<Row>>,
+}
+
+impl Dump {
+    pub fn rows(&self, table: &str) -> &[Row] {
+        self.tables.get(table).map(|t| t.as_slice()).unwrap_or(&[])
+    }
+}
+
+/// Splits on `;` outside of quotes, dropping comments
+fn statements(sql: &str) -> Vec<String> {
+    let mut statements = Vec::new();
+    let mut current = String::new();
+    let mut chars = sql.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' | '"' | '`' => {
+                current.push(c);
+                while let Some(q) = chars.next() {
+                    current.push(q);
+                    if q == '\\' && c != '`' {
+                        if let Some(escaped) = chars.next() {
+                            current.push(escaped);
+                        }
+                    } else if q == c {
+                        // a doubled quote is an escaped one
+                        if chars.peek() == Some(&c) {
+                            current.push(chars.next().unwrap());
+                        } else {
+                            break;
+                        }
+                    }
+                }
+            }
+            '-' if chars.peek() == Some(&'-') => {
+                for skipped in chars.by_ref() {
+                    if skipped == '\n' {
+                        break;
+                    }
+                }
+            }
+            '#' => {
+                for skipped in chars.by_ref() {
+                    if skipped == '\n' {
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                for skipped in chars.by_ref() {
+                    if previous == '*' && skipped == '/' {
+                        break;
+                    }
+                    previous = skipped;
+                }
+            }
+            ';' => statements.push(std::mem::take(&mut current)),
+            _ => current.push(c),
+        }
+    }
+    statements.push(current);
+
+    statements
+        .into_iter()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+struct Cursor<'a> {
+    chars: Vec<char>,
+    pos: usize,
+    statement: &'a str,
+}
+
+impl<'a> Cursor<'a> {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn error<T>(&self, message: &str) -> Result<T, String> {
+        let start: String = self.statement.chars().take(60).collect();
+        Err(format!(
+            "{} at character {} of `{}`",
+            message,
+            self.pos + 1,
+            start
+        ))
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        self.skip_whitespace();
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            self.error(&format!("expected `{}`", c))
+        }
+    }
+
+    /// A keyword or name, without its backquotes, e.g. `` `schema`.`table` `` is `schema.table`
+    fn word(&mut self) -> String {
+        self.skip_whitespace();
+        let mut word = String::new();
+        loop {
+            match self.peek() {
+                Some('`') => {
+                    self.pos += 1;
+                    while let Some(c) = self.peek() {
+                        self.pos += 1;
+                        if c == '`' {
+                            break;
+                        }
+                        word.push(c);
+                    }
+                }
+                Some(c) if c.is_alphanumeric() || c == '_' || c == '.' => {
+                    word.push(c);
+                    self.pos += 1;
+                }
+                _ => return word,
+            }
+        }
+    }
+
+    fn quoted(&mut self) -> Result<String, String> {
+        let quote = self.peek().unwrap();
+        self.pos += 1;
+        let mut value = String::new();
+        loop {
+            match self.peek() {
+                Some('\\') => {
+                    self.pos += 1;
+                    let escaped = match self.peek() {
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('0') => '\0',
+                        Some(c) => c,
+                        None => return self.error("unterminated string"),
+                    };
+                    value.push(escaped);
+                    self.pos += 1;
+                }
+                Some(c) if c == quote => {
+                    self.pos += 1;
+                    if self.peek() == Some(quote) {
+                        value.push(quote);
+                        self.pos += 1;
+                    } else {
+                        return Ok(value);
+                    }
+                }
+                Some(c) => {
+                    value.push(c);
+                    self.pos += 1;
+                }
+                None => return self.error("unterminated string"),
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Option<String>, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('\'') | Some('"') => self.quoted().map(Some),
+            _ => {
+                let mut value = String::new();
+                while let Some(c) = self
+                    .peek()
+                    .filter(|c| !matches!(c, ',' | ')') && !c.is_whitespace())
+                {
+                    value.push(c);
+                    self.pos += 1;
+                }
+                if value.is_empty() {
+                    self.error("expected a value")
+                } else if value.eq_ignore_ascii_case("null") {
+                    Ok(None)
+                } else {
+                    Ok(Some(value))
+                }
+            }
+        }
+    }
+
+    /// `(a, b, ..)` using `item` for each element
+    fn list<T>(&mut self, item: impl Fn(&mut Self) -> Result<T, String>) -> Result<Vec<T>, String> {
+        self.expect('(')?;
+        let mut items = Vec::new();
+        loop {
+            items.push(item(self)?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some(')') => {
+                    self.pos += 1;
+                    return Ok(items);
+                }
+                _ => return self.error("expected `,` or `)`"),
+            }
+        }
+    }
+}
+
+/// Reads one INSERT statement into (table, rows); the columns default to `columns_of(table)`
+fn insert(
+    statement: &str,
+    columns_of: &impl Fn(&str) -> Option<Vec<String>>,
+) -> Result<Option<(String, Vec<Row>)>, String> {
+    let mut cursor = Cursor {
+        chars: statement.chars().collect(),
+        pos: 0,
+        statement,
+    };
+
+    if !cursor.word().eq_ignore_ascii_case("insert") {
+        return Ok(None);
+    }
+    let mut keyword = cursor.word();
+    if keyword.eq_ignore_ascii_case("ignore") {
+        keyword = cursor.word();
+    }
+    if !keyword.eq_ignore_ascii_case("into") {
+        return cursor.error("expected INTO");
+    }
+    let table = cursor.word();
+    // `schema.table` keeps the table
+    let table = table.rsplit('.').next().unwrap_or_default().to_lowercase();
+
+    cursor.skip_whitespace();
+    // tables without column list nor known columns get numbered ones
+    let columns = if cursor.peek() == Some('(') {
+        Some(cursor.list(|c| Ok(c.word().to_lowercase()))?)
+    } else {
+        columns_of(&table)
+    };
+
+    if !cursor.word().eq_ignore_ascii_case("values") {
+        return cursor.error("expected VALUES");
+    }
+
+    let mut rows = Vec::new();
+    loop {
+        let values = cursor.list(Cursor::value)?;
+        let columns = columns
+            .clone()
+            .unwrap_or_else(|| (1..=values.len()).map(|i| i.to_string()).collect());
+        if values.len() != columns.len() {
+            return cursor.error(&format!(
+                "{} values for {} columns",
+                values.len(),
+                columns.len()
+            ));
+        }
+        rows.push(columns.into_iter().zip(values).collect());
+
+        cursor.skip_whitespace();
+        if cursor.peek() == Some(',') {
+            cursor.pos += 1;
+        } else {
+            break;
+        }
+    }
+
+    Ok(Some((table, rows)))
+}
+
+/// Reads every INSERT of the dump. `columns_of` gives the columns of the
+/// tables whose statements have no column list.
+pub fn parse(sql: &str, columns_of: impl Fn(&str) -> Option<Vec<String>>) -> Result<Dump, String> {
+    let mut dump = Dump::default();
+    for statement in statements(sql) {
+        if let Some((table, mut rows)) = insert(&statement, &columns_of)? {
+            dump.tables.entry(table).or_default().append(&mut rows);
+        }
+    }
+
+    Ok(dump)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DUMP: &str = include_str!("../../res/test/spring_dump.sql");
+
+    fn no_columns(_: &str) -> Option<Vec<String>> {
+        None
+    }
+
+    fn value<'a>(row: &'a Row, column: &str) -> Option<&'a str> {
+        row.get(column).unwrap().as_deref()
+    }
+
+    #[test]
+    fn statements_skip_comments_and_keep_quoted_semicolons() {
+        let sql = "-- a comment; still the comment\n\
+                   # another one\n\
+                   /* a block; comment */ select 1;\
+                   insert into t values ('a;b', \"c -- d\", `e;f`);;";
+
+        assert_eq!(
+            statements(sql),
+            vec![
+                "select 1",
+                "insert into t values ('a;b', \"c -- d\", `e;f`)",
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_the_inserts_of_a_dump() {
+        let columns_of = |table: &str| match table {
+            "vets" => Some(vec![
+                "id".to_string(),
+                "first_name".to_string(),
+                "last_name".to_string(),
+            ]),
+            _ => None,
+        };
+        let dump = parse(DUMP, columns_of).unwrap();
+
+        let mut tables: Vec<&str> = dump.tables.keys().map(|t| t.as_str()).collect();
+        tables.sort_unstable();
+        assert_eq!(
+            tables,
+            vec![
+                "audit_log",
+                "owners",
+                "pets",
+                "specialties",
+                "types",
+                "vet_specialties",
+                "vets",
+                "visits",
+            ]
+        );
+
+        // columns given by `columns_of`
+        let vets = dump.rows("vets");
+        assert_eq!(vets.len(), 2);
+        assert_eq!(value(&vets[1], "first_name"), Some("Helen"));
+
+        // columns of the statement, the schema name dropped
+        let owners = dump.rows("owners");
+        assert_eq!(owners.len(), 3);
+        assert_eq!(value(&owners[0], "address"), Some("110 W. Liberty St."));
+        assert_eq!(value(&owners[1], "address"), Some(""));
+
+        // numbered columns otherwise, NULL read as none
+        let visits = dump.rows("visits");
+        assert_eq!(visits.len(), 4);
+        assert_eq!(value(&visits[0], "4"), Some("rabies shot; it's done"));
+        assert_eq!(value(&visits[2], "4"), None);
+        assert_eq!(value(&dump.rows("pets")[4], "2"), Some("Iggy"));
+
+        assert!(dump.rows("owner").is_empty());
+    }
+
+    #[test]
+    fn unescapes_strings() {
+        let dump = parse(
+            r#"insert into t (a, b, c, d) values ('O\'Brien', 'two\nlines', "say ""hi""", 'a\\b')"#,
+            no_columns,
+        )
+        .unwrap();
+        let row = &dump.rows("t")[0];
+
+        assert_eq!(value(row, "a"), Some("O'Brien"));
+        assert_eq!(value(row, "b"), Some("two\nlines"));
+        assert_eq!(value(row, "c"), Some("say \"hi\""));
+        assert_eq!(value(row, "d"), Some("a\\b"));
+    }
+
+    #[test]
+    fn skips_other_statements() {
+        let dump = parse(
+            "create table t (id int); update t set id = 2; lock tables t write",
+            no_columns,
+        )
+        .unwrap();
+
+        assert!(dump.tables.is_empty());
+    }
+
+    #[test]
+    fn errors() {
+        let e = parse("insert into t (a, b) values (1)", no_columns).unwrap_err();
+        assert!(
+            e.starts_with("1 values for 2 columns at character"),
+            "{}",
+            e
+        );
+
+        let e = parse("insert t values (1)", no_columns).unwrap_err();
+        assert_eq!(e, "expected INTO at character 9 of `insert t values (1)`");
+
+        let e = parse("insert into t (a) (1)", no_columns).unwrap_err();
+        assert!(e.starts_with("expected VALUES"), "{}", e);
+
+        let e = parse("insert into t values (1 2)", no_columns).unwrap_err();
+        assert!(e.starts_with("expected `,` or `)`"), "{}", e);
+
+        let e = parse("insert into t values (1, 'abc", no_columns).unwrap_err();
+        assert!(e.starts_with("unterminated string"), "{}", e);
+    }
+}
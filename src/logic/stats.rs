@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+
+use chrono::{naive::NaiveDate, Datelike, Duration, Utc};
+use rbatis::rbatis::Rbatis;
+use rbson::Bson;
+use serde::{Deserialize, Serialize};
+
+use super::{clinics::Scope, pets};
+
+/// Months shown in the registrations chart, the current one included
+pub const MONTHS: u32 = 12;
+/// Weeks shown in the visits chart, the current one included
+pub const WEEKS: i64 = 12;
+
+#[derive(Deserialize)]
+struct CountRow {
+    label: Option<String>,
+    count: u64,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Bar {
+    pub label: String,
+    pub count: u64,
+    /// size relative to the largest bar, 0 to 100
+    pub percent: u64,
+}
+
+/// Values of a chart, in display order
+#[derive(Serialize, Clone, Debug)]
+pub struct Series {
+    pub bars: Vec<Bar>,
+    pub total: u64,
+}
+
+impl Series {
+    fn new(counts: Vec<(String, u64)>) -> Series {
+        let max = counts.iter().map(|(_, c)| *c).max().unwrap_or(0);
+        let total = counts.iter().map(|(_, c)| *c).sum();
+        let bars = counts
+            .into_iter()
+            .map(|(label, count)| Bar {
+                label,
+                count,
+                percent: (count * 100).checked_div(max).unwrap_or(0),
+            })
+            .collect();
+
+        Series { bars, total }
+    }
+}
+
+async fn counts(
+    rb: &Rbatis,
+    sql: &str,
+    scope: &Scope,
+    column: &str,
+    mut args: Vec<Bson>,
+) -> Result<Vec<CountRow>, rbatis::Error> {
+    let clause = scope.sql(column, &mut args);
+
+    rb.fetch(&sql.replace("{scope}", &clause), args).await
+}
+
+pub async fn pets_by_type(rb: &Rbatis, scope: &Scope) -> Result<Series, rbatis::Error> {
+    let rows = counts(
+        rb,
+        "select cast(pet_type as char) as label, count(*) as count from pet \
+         where true{scope} group by pet_type order by count desc",
+        scope,
+        "clinic_id",
+        vec![],
+    )
+    .await?;
+
+    Ok(Series::new(
+        rows.into_iter()
+            .map(|r| {
+                let label = r
+                    .label
+                    .and_then(|id| id.parse().ok())
+                    .map(pets::type_label)
+                    .unwrap_or_default();
+                (label, r.count)
+            })
+            .collect(),
+    ))
+}
+
+pub async fn pets_per_vet(rb: &Rbatis, scope: &Scope) -> Result<Series, rbatis::Error> {
+    let rows = counts(
+        rb,
+        "select v.name as label, count(*) as count from pet p left join vet v on v.id = p.vet_id \
+         where true{scope} group by p.vet_id, v.name order by count desc",
+        scope,
+        "p.clinic_id",
+        vec![],
+    )
+    .await?;
+
+    Ok(Series::new(
+        rows.into_iter()
+            .map(|r| (r.label.unwrap_or_else(|| "Unassigned".to_string()), r.count))
+            .collect(),
+    ))
+}
+
+fn month_start(date: NaiveDate, months_back: u32) -> NaiveDate {
+    let months = date.year() * 12 + date.month0() as i32 - months_back as i32;
+    NaiveDate::from_ymd(months.div_euclid(12), months.rem_euclid(12) as u32 + 1, 1)
+}
+
+/// Pets registered in each of the last `MONTHS` months, empty months included
+pub async fn registrations_per_month(rb: &Rbatis, scope: &Scope) -> Result<Series, rbatis::Error> {
+    let today = Utc::now().naive_utc().date();
+    let first = month_start(today, MONTHS - 1);
+
+    let rows = counts(
+        rb,
+        "select date_format(created_at, '%Y-%m') as label, count(*) as count from pet \
+         where created_at >= ?{scope} group by label",
+        scope,
+        "clinic_id",
+        vec![Bson::from(first.and_hms(0, 0, 0).to_string())],
+    )
+    .await?;
+    let found: HashMap<String, u64> = rows
+        .into_iter()
+        .filter_map(|r| Some((r.label?, r.count)))
+        .collect();
+
+    Ok(Series::new(
+        (0..MONTHS)
+            .rev()
+            .map(|back| {
+                let month = month_start(today, back);
+                let key = month.format("%Y-%m").to_string();
+                (
+                    month.format("%b %Y").to_string(),
+                    found.get(&key).copied().unwrap_or(0),
+                )
+            })
+            .collect(),
+    ))
+}
+
+/// Visits in each of the last `WEEKS` weeks, starting on Monday, empty weeks included
+pub async fn visits_per_week(rb: &Rbatis, scope: &Scope) -> Result<Series, rbatis::Error> {
+    let today = Utc::now().naive_utc().date();
+    let this_week = today - Duration::days(today.weekday().num_days_from_monday() as i64);
+    let first = this_week - Duration::weeks(WEEKS - 1);
+
+    let rows = counts(
+        rb,
+        "select date_format(date_sub(v.visit_date, interval weekday(v.visit_date) day), '%Y-%m-%d') \
+         as label, count(*) as count from visit v join pet p on p.id = v.pet_id \
+         where v.visit_date >= ?{scope} group by label",
+        scope,
+        "p.clinic_id",
+        vec![Bson::from(first.and_hms(0, 0, 0).to_string())],
+    )
+    .await?;
+    let found: HashMap<String, u64> = rows
+        .into_iter()
+        .filter_map(|r| Some((r.label?, r.count)))
+        .collect();
+
+    Ok(Series::new(
+        (0..WEEKS)
+            .map(|week| {
+                let monday = first + Duration::weeks(week);
+                let key = monday.format("%Y-%m-%d").to_string();
+                (
+                    monday.format("%b %-d").to_string(),
+                    found.get(&key).copied().unwrap_or(0),
+                )
+            })
+            .collect(),
+    ))
+}
+
+/// Caseload of a vet over a period
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Workload {
+    pub vet_id: u32,
+    pub name: String,
+    /// pets currently assigned, whatever the period
+    pub pets: u64,
+    pub visits: u64,
+    #[serde(default)]
+    pub visits_per_day: f64,
+}
+
+/// Every active vet, and every vet with visits in the period even if deactivated since, with
+/// their assigned pets and the visits from `from` to `to`, both days included
+pub async fn workload(
+    rb: &Rbatis,
+    scope: &Scope,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Vec<Workload>, rbatis::Error> {
+    let mut args = vec![Bson::from(from.and_hms(0, 0, 0).to_string())];
+    // the last date of all has no day after it, the period is then left open
+    let until = match to.checked_add_signed(Duration::days(1)) {
+        Some(next_day) => {
+            args.push(Bson::from(next_day.and_hms(0, 0, 0).to_string()));
+            " and vi.visit_date < ?"
+        }
+        None => "",
+    };
+    let clause = scope.sql("v.clinic_id", &mut args);
+    let mut rows: Vec<Workload> = rb
+        .fetch(
+            &format!(
+                "select vet_id, name, pets, visits from (\
+                 select v.id as vet_id, v.name, v.active, \
+                 (select count(*) from pet p where p.vet_id = v.id) as pets, \
+                 (select count(*) from visit vi where vi.vet_id = v.id \
+                 and vi.visit_date >= ?{}) as visits \
+                 from vet v where true{}) w \
+                 where active = true or visits > 0 order by name",
+                until, clause
+            ),
+            args,
+        )
+        .await?;
+
+    let days = ((to - from).num_days() + 1).max(1) as f64;
+    for row in &mut rows {
+        row.visits_per_day = row.visits as f64 / days;
+    }
+
+    Ok(rows)
+}
@@ -0,0 +1,175 @@
+use axum::http::Method;
+use chrono::{naive::NaiveDateTime, Duration, Utc};
+use rand::{distributions::Alphanumeric, Rng};
+use rbatis::{crud::CRUD, crud_table, rbatis::Rbatis};
+use rbson::Bson;
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+
+use super::users::User;
+
+/// Start of every token, so leaked ones are easy to recognize
+const TOKEN_PREFIX: &str = "pct_";
+/// Random characters after the prefix
+const TOKEN_LENGTH: usize = 40;
+/// Characters kept in clear to tell the tokens apart in the list
+const SHOWN_LENGTH: usize = 8;
+/// Lifetimes offered when creating a token
+pub const EXPIRY_DAYS: &[i64] = &[7, 30, 90, 365];
+
+/// What an API token may do
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenScope {
+    /// GET requests only
+    Read,
+    /// every request of the API
+    Write,
+}
+
+impl TokenScope {
+    pub fn from_param(value: &str) -> Option<TokenScope> {
+        match value {
+            "read" => Some(TokenScope::Read),
+            "write" => Some(TokenScope::Write),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenScope::Read => "read",
+            TokenScope::Write => "write",
+        }
+    }
+
+    /// Whether a request to `path` may be made. `/graphql` only has queries, a read whatever
+    /// the method.
+    pub fn allows(&self, method: &Method, path: &str) -> bool {
+        match self {
+            TokenScope::Read => {
+                method == Method::GET || method == Method::HEAD || path == "/graphql"
+            }
+            TokenScope::Write => true,
+        }
+    }
+}
+
+/// Personal access token of a user, only its hash is stored
+#[crud_table]
+#[derive(Clone)]
+pub struct ApiToken {
+    pub id: u32,
+    pub user_id: u32,
+    pub name: String,
+    /// first characters of the token, shown in the list
+    pub prefix: String,
+    pub token_hash: String,
+    /// "read" or "write", see `TokenScope`
+    pub scope: String,
+    pub created_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+    pub last_used_at: Option<NaiveDateTime>,
+}
+
+impl ApiToken {
+    pub fn scope(&self) -> TokenScope {
+        TokenScope::from_param(&self.scope).unwrap_or(TokenScope::Read)
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= Utc::now().naive_utc()
+    }
+}
+
+fn hash(token: &str) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(token);
+
+    format!("{:x}", hasher.finalize())
+}
+
+fn generate() -> String {
+    let random: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(TOKEN_LENGTH)
+        .map(char::from)
+        .collect();
+
+    format!("{}{}", TOKEN_PREFIX, random)
+}
+
+pub async fn of_user(rb: &Rbatis, user: &User) -> Result<Vec<ApiToken>, rbatis::Error> {
+    let w = rb
+        .new_wrapper()
+        .eq("user_id", user.id)
+        .order_by(false, &["created_at"]);
+
+    rb.fetch_list_by_wrapper(w).await
+}
+
+/// Creates a token valid for `days`, returns it in clear: this is the only time it is known
+pub async fn create(
+    rb: &Rbatis,
+    user: &User,
+    name: &str,
+    scope: TokenScope,
+    days: i64,
+) -> Result<String, rbatis::Error> {
+    let secret = generate();
+    let now = Utc::now().naive_utc();
+    let token = ApiToken {
+        id: 0,
+        user_id: user.id,
+        name: name.to_string(),
+        prefix: secret[..TOKEN_PREFIX.len() + SHOWN_LENGTH].to_string(),
+        token_hash: hash(&secret),
+        scope: scope.as_str().to_string(),
+        created_at: now,
+        expires_at: now + Duration::days(days),
+        last_used_at: None,
+    };
+    rb.save(&token, &[]).await?;
+
+    Ok(secret)
+}
+
+/// Deletes a token of `user`, false when there is no such token
+pub async fn revoke(rb: &Rbatis, user: &User, id: u32) -> Result<bool, rbatis::Error> {
+    let result = rb
+        .exec(
+            "delete from api_token where id = ? and user_id = ?",
+            vec![Bson::from(id), Bson::from(user.id)],
+        )
+        .await?;
+
+    Ok(result.rows_affected > 0)
+}
+
+/// The user of a valid, unexpired token, also recording when it was last used
+pub async fn authenticate(
+    rb: &Rbatis,
+    secret: &str,
+) -> Result<Option<(User, ApiToken)>, rbatis::Error> {
+    let w = rb.new_wrapper().eq("token_hash", hash(secret));
+    let token: ApiToken = match rb.fetch_by_wrapper(w).await? {
+        Some(token) => token,
+        None => return Ok(None),
+    };
+    if token.is_expired() {
+        return Ok(None);
+    }
+
+    let w = rb.new_wrapper().eq("id", token.user_id);
+    let user: Option<User> = rb.fetch_by_wrapper(w).await?;
+    rb.exec(
+        "update api_token set last_used_at = ? where id = ?",
+        vec![
+            Bson::from(Utc::now().naive_utc().to_string()),
+            Bson::from(token.id),
+        ],
+    )
+    .await?;
+
+    Ok(user.map(|user| (user, token)))
+}
@@ -11,14 +11,25 @@ pub struct User {
     pub id: u32,
     pub username: String,
     pub password: String,
+    /// home clinic, active by default after login
+    pub clinic_id: u32,
+    /// admins can work on every clinic and search across them
+    pub is_admin: bool,
 }
 
 impl FromRedisValue for User {
     fn from_redis_value(v: &redis::Value) -> redis::RedisResult<Self> {
         if let redis::Value::Data(u) = v {
-            let s = String::from_utf8_lossy(&*u);
-            let user: User = serde_json::from_str(&s).unwrap();
-            return Ok(user);
+            let s = String::from_utf8_lossy(u);
+            // sessions stored by an older version lack fields, their users log in again
+            return serde_json::from_str(&s).map_err(|e| {
+                (
+                    ErrorKind::TypeError,
+                    "Stored user not understood",
+                    e.to_string(),
+                )
+                    .into()
+            });
         }
 
        Err((ErrorKind::TypeError, "Parse to JSON Failed").into())
@@ -55,6 +66,23 @@ pub async fn authenticate(
     Ok(None)
 }
 
+pub async fn by_ids(rb: &Rbatis, ids: &[u32]) -> Result<Vec<User>, rbatis::Error> {
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let w = rb.new_wrapper().r#in("id", ids);
+
+    rb.fetch_list_by_wrapper(w).await
+}
+
+/// Hex SHA-1 of a password, as stored in the user table
+pub fn hash_password(password: &str) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(password);
+
+    format!("{:x}", hasher.finalize())
+}
+
 pub fn session_key() -> String {
     let s: String = rand::thread_rng()
         .sample_iter(&Alphanumeric)
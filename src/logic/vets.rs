@@ -1,10 +1,43 @@
-use rbatis::{crud::CRUD, crud_table, rbatis::Rbatis};
+use std::collections::HashMap;
+
+use chrono::{NaiveDateTime, Utc};
+
+use rbatis::{
+    crud::{CRUDMut, CRUD},
+    crud_table,
+    executor::ExecutorMut,
+    plugin::page::Page,
+    rbatis::Rbatis,
+};
+use rbson::Bson;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::{clinics::Scope, paging::Paging, pets::Pet};
 
 #[crud_table]
-#[derive(Default)]
+#[derive(Default, Clone, ToSchema)]
 pub struct Vet {
     pub id: u32,
     pub name: String,
+    pub active: bool,
+    pub clinic_id: u32,
+    /// last change, none for the vets that were never changed through the application
+    #[serde(default)]
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+/// Number of records still pointing at a vet, used to decide whether it can be removed
+#[derive(Deserialize, Serialize, Default, ToSchema)]
+pub struct Dependents {
+    pub pets: u64,
+    pub visits: u64,
+}
+
+impl Dependents {
+    pub fn is_empty(&self) -> bool {
+        self.pets == 0 && self.visits == 0
+    }
 }
 
 pub async fn delete(rb: &Rbatis, vet: &Vet) -> Result<(), rbatis::Error> {
@@ -13,28 +46,139 @@ pub async fn delete(rb: &Rbatis, vet: &Vet) -> Result<(), rbatis::Error> {
     Ok(())
 }
 
-pub async fn search(rb: &Rbatis, name: Option<&String>) -> Result<Vec<Vet>, rbatis::Error> {
-    let w = rb
-        .new_wrapper()
+pub async fn dependents(rb: &Rbatis, vet: &Vet) -> Result<Dependents, rbatis::Error> {
+    let d: Dependents = rb
+        .fetch(
+            "select (select count(*) from pet where vet_id = ?) as pets, \
+             (select count(*) from visit where vet_id = ?) as visits",
+            vec![Bson::from(vet.id), Bson::from(vet.id)],
+        )
+        .await?;
+
+    Ok(d)
+}
+
+/// Moves every pet of `vet` to `target` (or leaves them unassigned when `None`)
+/// and deactivates `vet`, all in a single transaction. Returns the moved pets.
+pub async fn reassign_and_deactivate(
+    rb: &Rbatis,
+    vet: &Vet,
+    target: Option<u32>,
+) -> Result<Vec<Pet>, rbatis::Error> {
+    let mut tx = rb.acquire_begin().await?;
+
+    let now = Utc::now().naive_utc();
+    let result = async {
+        let w = rb.new_wrapper().eq("vet_id", vet.id);
+        let moved: Vec<Pet> = tx.fetch_list_by_wrapper(w).await?;
+        tx.exec(
+            "update pet set vet_id = ?, updated_at = ? where vet_id = ?",
+            vec![
+                target.map(Bson::from).unwrap_or(Bson::Null),
+                Bson::from(now.to_string()),
+                Bson::from(vet.id),
+            ],
+        )
+        .await?;
+        tx.exec(
+            "update vet set active = false, updated_at = ? where id = ?",
+            vec![Bson::from(now.to_string()), Bson::from(vet.id)],
+        )
+        .await?;
+        Ok(moved)
+    }
+    .await;
+
+    match result {
+        Ok(moved) => {
+            tx.commit().await?;
+            Ok(moved
+                .into_iter()
+                .map(|pet| Pet {
+                    vet_id: target,
+                    updated_at: Some(now),
+                    ..pet
+                })
+                .collect())
+        }
+        Err(e) => {
+            tx.rollback().await?;
+            Err(e)
+        }
+    }
+}
+
+/// Columns the vet list can be sorted by, the first one is the default
+pub const SORTABLE: &[&str] = &["name", "id"];
+
+pub async fn search(
+    rb: &Rbatis,
+    scope: &Scope,
+    name: Option<&String>,
+    paging: &Paging,
+) -> Result<Page<Vet>, rbatis::Error> {
+    let w = scope
+        .filter(rb.new_wrapper())
+        .eq("active", true)
         .like("name", name.unwrap_or(&String::new()));
 
+    let vet_page: Page<Vet> = rb
+        .fetch_page_by_wrapper(paging.order(w), &paging.request())
+        .await?;
+
+    Ok(vet_page)
+}
+
+/// Every active vet, for the assignment drop downs
+pub async fn active(rb: &Rbatis, scope: &Scope) -> Result<Vec<Vet>, rbatis::Error> {
+    let w = scope
+        .filter(rb.new_wrapper())
+        .eq("active", true)
+        .order_by(true, &["name"]);
+
     let vet_list: Vec<Vet> = rb.fetch_list_by_wrapper(w).await?;
 
     Ok(vet_list)
 }
 
-pub async fn get(rb: &Rbatis, id: u32) -> Result<Option<Vet>, rbatis::Error> {
-    let v = rb.fetch_by_column("id", id).await?;
+/// Names of every vet of the scope, including the inactive ones, by id
+pub async fn names(rb: &Rbatis, scope: &Scope) -> Result<HashMap<u32, String>, rbatis::Error> {
+    let vet_list: Vec<Vet> = rb
+        .fetch_list_by_wrapper(scope.filter(rb.new_wrapper()))
+        .await?;
+
+    Ok(vet_list.into_iter().map(|v| (v.id, v.name)).collect())
+}
+
+pub async fn get(rb: &Rbatis, scope: &Scope, id: u32) -> Result<Option<Vet>, rbatis::Error> {
+    let w = scope.filter(rb.new_wrapper()).eq("id", id);
+    let v = rb.fetch_by_wrapper(w).await?;
 
     Ok(v)
 }
 
-pub async fn save(rb: &Rbatis, vet: &Vet) -> Result<(), rbatis::Error> {
+/// Vets of the scope among `ids`, inactive ones included, for batched lookups
+pub async fn by_ids(rb: &Rbatis, scope: &Scope, ids: &[u32]) -> Result<Vec<Vet>, rbatis::Error> {
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let w = scope.filter(rb.new_wrapper()).r#in("id", ids);
+
+    rb.fetch_list_by_wrapper(w).await
+}
+
+/// Inserts new vets (id 0) or updates existing ones, returns the id
+pub async fn save(rb: &Rbatis, vet: &Vet) -> Result<u32, rbatis::Error> {
+    let vet = Vet {
+        updated_at: Some(Utc::now().naive_utc()),
+        ..vet.clone()
+    };
     if vet.id == 0 {
-        rb.save(&vet, &[]).await?;
-    } else {
-        let w = rb.new_wrapper().eq("id", vet.id);
-        rb.update_by_wrapper(&vet, w, &[]).await?;
+        let result = rb.save(&vet, &[]).await?;
+        return Ok(result.last_insert_id.unwrap_or_default() as u32);
     }
-    Ok(())
+    let w = rb.new_wrapper().eq("id", vet.id);
+    rb.update_by_wrapper(&vet, w, &[]).await?;
+
+    Ok(vet.id)
 }
@@ -0,0 +1,132 @@
+use chrono::naive::NaiveDateTime;
+use rbatis::{crud::CRUD, crud_table, plugin::page::Page, rbatis::Rbatis, wrapper::Wrapper};
+use rbson::Bson;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::{clinics::Scope, paging::Paging};
+
+#[crud_table]
+#[derive(Default, Clone, ToSchema)]
+pub struct Visit {
+    pub id: u32,
+    pub pet_id: u32,
+    pub vet_id: u32,
+    pub visit_date: NaiveDateTime,
+    pub notes: Option<String>,
+}
+
+/// A visit with the names of the pet and the vet, for listings and exports
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct VisitDetail {
+    pub id: u32,
+    pub pet_id: u32,
+    pub pet_name: Option<String>,
+    pub vet_id: u32,
+    pub vet_name: Option<String>,
+    pub visit_date: NaiveDateTime,
+    pub notes: Option<String>,
+}
+
+pub async fn of_pets(rb: &Rbatis, pet_ids: &[u32]) -> Result<Vec<VisitDetail>, rbatis::Error> {
+    if pet_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let placeholders = vec!["?"; pet_ids.len()].join(", ");
+
+    rb.fetch(
+        &format!(
+            "select v.id, v.pet_id, p.name as pet_name, v.vet_id, vt.name as vet_name, \
+             v.visit_date, v.notes \
+             from visit v join pet p on p.id = v.pet_id join vet vt on vt.id = v.vet_id \
+             where v.pet_id in ({}) order by v.visit_date, v.id",
+            placeholders
+        ),
+        pet_ids.iter().map(|id| Bson::from(*id)).collect(),
+    )
+    .await
+}
+
+/// Columns the visit list can be sorted by, the first one is the default
+pub const SORTABLE: &[&str] = &["visit_date", "id"];
+
+/// Visits have no clinic of their own, they belong to the clinic of their pet
+fn scoped(scope: &Scope, w: Wrapper) -> Wrapper {
+    match scope {
+        Scope::Clinic(id) => w
+            .and()
+            .push_sql("pet_id in (select id from pet where clinic_id = ?)")
+            .push_arg(id),
+        Scope::All => w,
+    }
+}
+
+pub async fn search(
+    rb: &Rbatis,
+    scope: &Scope,
+    pet_id: Option<u32>,
+    vet_id: Option<u32>,
+    paging: &Paging,
+) -> Result<Page<Visit>, rbatis::Error> {
+    let mut w = scoped(scope, rb.new_wrapper());
+    if let Some(pet_id) = pet_id {
+        w = w.eq("pet_id", pet_id);
+    }
+    if let Some(vet_id) = vet_id {
+        w = w.eq("vet_id", vet_id);
+    }
+
+    rb.fetch_page_by_wrapper(paging.order(w), &paging.request())
+        .await
+}
+
+pub async fn get(rb: &Rbatis, scope: &Scope, id: u32) -> Result<Option<Visit>, rbatis::Error> {
+    let w = scoped(scope, rb.new_wrapper()).eq("id", id);
+
+    rb.fetch_by_wrapper(w).await
+}
+
+/// The `per_pet` latest visits of each of `pet_ids`, latest first
+pub async fn by_pets(
+    rb: &Rbatis,
+    pet_ids: &[u32],
+    per_pet: usize,
+) -> Result<Vec<Visit>, rbatis::Error> {
+    if pet_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let placeholders = vec!["?"; pet_ids.len()].join(", ");
+    let mut args: Vec<Bson> = pet_ids.iter().map(|id| Bson::from(*id)).collect();
+    args.push(Bson::from(per_pet as u32));
+
+    rb.fetch(
+        &format!(
+            "select id, pet_id, vet_id, visit_date, notes from (\
+             select v.*, row_number() over \
+             (partition by v.pet_id order by v.visit_date desc, v.id desc) as n \
+             from visit v where v.pet_id in ({})) ranked \
+             where n <= ? order by visit_date desc, id desc",
+            placeholders
+        ),
+        args,
+    )
+    .await
+}
+
+/// Inserts new visits (id 0) or updates existing ones, returns the id
+pub async fn save(rb: &Rbatis, visit: &Visit) -> Result<u32, rbatis::Error> {
+    if visit.id == 0 {
+        let result = rb.save(visit, &[]).await?;
+        return Ok(result.last_insert_id.unwrap_or_default() as u32);
+    }
+    let w = rb.new_wrapper().eq("id", visit.id);
+    rb.update_by_wrapper(visit, w, &[]).await?;
+
+    Ok(visit.id)
+}
+
+pub async fn delete(rb: &Rbatis, visit: &Visit) -> Result<(), rbatis::Error> {
+    rb.remove_by_column::<Visit, _>("id", &visit.id).await?;
+
+    Ok(())
+}
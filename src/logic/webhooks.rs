@@ -0,0 +1,358 @@
+use chrono::{naive::NaiveDateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use rand::{distributions::Alphanumeric, Rng};
+use rbatis::{crud::CRUD, crud_table, rbatis::Rbatis};
+use rbson::Bson;
+use serde::Serialize;
+use serde_json::json;
+use sha2::Sha256;
+
+use crate::Context;
+
+use std::sync::Arc;
+
+pub const PET_CREATED: &str = "pet.created";
+pub const PET_UPDATED: &str = "pet.updated";
+pub const PET_DELETED: &str = "pet.deleted";
+pub const VISIT_CREATED: &str = "visit.created";
+/// Events a webhook can subscribe to
+pub const EVENTS: &[&str] = &[PET_CREATED, PET_UPDATED, PET_DELETED, VISIT_CREATED];
+/// Sent on demand from the webhook page, whatever the subscribed events
+pub const PING: &str = "ping";
+
+pub const PENDING: &str = "pending";
+pub const DELIVERED: &str = "delivered";
+pub const FAILED: &str = "failed";
+
+/// Attempts before a delivery is given up
+pub const MAX_ATTEMPTS: u32 = 8;
+/// Wait before the first retry, doubled after each failed attempt up to `MAX_RETRY_SECS`
+const FIRST_RETRY_SECS: i64 = 30;
+const MAX_RETRY_SECS: i64 = 6 * 3600;
+/// How often the worker looks for due deliveries
+const POLL_SECS: u64 = 10;
+/// Deliveries sent per poll
+const BATCH_SIZE: u64 = 20;
+const TIMEOUT_SECS: u64 = 10;
+/// Time a worker has to send a delivery it claimed before another one may retry it
+const LEASE_SECS: i64 = 60;
+/// Delivery log entries shown per webhook
+const LOG_SIZE: u64 = 100;
+const MAX_ERROR_LENGTH: usize = 500;
+
+/// Subscription of a URL to events of a clinic
+#[crud_table]
+#[derive(Clone)]
+pub struct Webhook {
+    pub id: u32,
+    pub clinic_id: u32,
+    pub url: String,
+    /// comma separated names from `EVENTS`
+    pub events: String,
+    /// key of the HMAC signature of the payloads
+    pub secret: String,
+    pub active: bool,
+    pub created_at: NaiveDateTime,
+    pub created_by: u32,
+}
+
+impl Webhook {
+    pub fn wants(&self, event: &str) -> bool {
+        self.events.split(',').any(|e| e == event)
+    }
+}
+
+/// One event to send to one webhook, kept as the delivery log
+#[crud_table]
+#[derive(Clone)]
+pub struct WebhookDelivery {
+    pub id: u32,
+    pub webhook_id: u32,
+    pub event: String,
+    pub payload: String,
+    /// `PENDING`, `DELIVERED` or `FAILED`
+    pub status: String,
+    pub attempts: u32,
+    pub next_attempt_at: NaiveDateTime,
+    /// HTTP status of the last attempt, when the receiver answered
+    pub response_status: Option<u32>,
+    pub last_error: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub delivered_at: Option<NaiveDateTime>,
+}
+
+pub fn new_secret() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect()
+}
+
+/// Hex HMAC-SHA256 of `<timestamp>.<body>`, sent as `X-Petclinic-Signature: sha256=<hex>`
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("any key length");
+    mac.update(format!("{}.{}", timestamp, body).as_bytes());
+
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Wait after the `attempts`th failed attempt
+pub fn retry_delay(attempts: u32) -> Duration {
+    let secs = FIRST_RETRY_SECS
+        .checked_shl(attempts.saturating_sub(1))
+        .unwrap_or(MAX_RETRY_SECS);
+
+    Duration::seconds(secs.min(MAX_RETRY_SECS))
+}
+
+pub async fn of_clinic(rb: &Rbatis, clinic_id: u32) -> Result<Vec<Webhook>, rbatis::Error> {
+    let w = rb
+        .new_wrapper()
+        .eq("clinic_id", clinic_id)
+        .order_by(true, &["url"]);
+
+    rb.fetch_list_by_wrapper(w).await
+}
+
+pub async fn get(rb: &Rbatis, clinic_id: u32, id: u32) -> Result<Option<Webhook>, rbatis::Error> {
+    let w = rb.new_wrapper().eq("clinic_id", clinic_id).eq("id", id);
+
+    rb.fetch_by_wrapper(w).await
+}
+
+/// Inserts new webhooks (id 0) or updates existing ones
+pub async fn save(rb: &Rbatis, hook: &Webhook) -> Result<(), rbatis::Error> {
+    if hook.id == 0 {
+        rb.save(hook, &[]).await?;
+        return Ok(());
+    }
+    let w = rb.new_wrapper().eq("id", hook.id);
+    rb.update_by_wrapper(hook, w, &[]).await?;
+
+    Ok(())
+}
+
+/// Removes the webhook with its delivery log
+pub async fn delete(rb: &Rbatis, hook: &Webhook) -> Result<(), rbatis::Error> {
+    rb.remove_by_column::<Webhook, _>("id", &hook.id).await?;
+
+    Ok(())
+}
+
+/// Latest deliveries of a webhook
+pub async fn deliveries(
+    rb: &Rbatis,
+    hook: &Webhook,
+) -> Result<Vec<WebhookDelivery>, rbatis::Error> {
+    let w = rb
+        .new_wrapper()
+        .eq("webhook_id", hook.id)
+        .order_bys(&[("created_at", false), ("id", false)])
+        .limit(LOG_SIZE);
+
+    rb.fetch_list_by_wrapper(w).await
+}
+
+/// Queues a delivery again, with a fresh set of attempts
+pub async fn redeliver(rb: &Rbatis, hook: &Webhook, delivery_id: u32) -> Result<(), rbatis::Error> {
+    rb.exec(
+        "update webhook_delivery set status = ?, attempts = 0, next_attempt_at = ? \
+         where id = ? and webhook_id = ?",
+        vec![
+            Bson::from(PENDING),
+            Bson::from(Utc::now().naive_utc().to_string()),
+            Bson::from(delivery_id),
+            Bson::from(hook.id),
+        ],
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Queues `event` for `hook`, the worker sends it on its next poll
+pub async fn enqueue(
+    rb: &Rbatis,
+    hook: &Webhook,
+    event: &str,
+    data: &serde_json::Value,
+) -> Result<(), rbatis::Error> {
+    let now = Utc::now().naive_utc();
+    let payload = json!({
+        "event": event,
+        "clinic_id": hook.clinic_id,
+        "created_at": now,
+        "data": data,
+    });
+    let delivery = WebhookDelivery {
+        id: 0,
+        webhook_id: hook.id,
+        event: event.to_string(),
+        payload: payload.to_string(),
+        status: PENDING.to_string(),
+        attempts: 0,
+        next_attempt_at: now,
+        response_status: None,
+        last_error: None,
+        created_at: now,
+        delivered_at: None,
+    };
+    rb.save(&delivery, &[]).await?;
+
+    Ok(())
+}
+
+/// Queues `event` for every active webhook of the clinic subscribed to it. Errors are only
+/// logged: a webhook must not make the change that triggered it fail.
+pub async fn publish(rb: &Rbatis, clinic_id: u32, event: &str, data: &impl Serialize) {
+    let result = async {
+        let data = serde_json::to_value(data).map_err(|e| rbatis::Error::from(e.to_string()))?;
+        for hook in of_clinic(rb, clinic_id).await? {
+            if hook.active && hook.wants(event) {
+                enqueue(rb, &hook, event, &data).await?;
+            }
+        }
+        Ok::<(), rbatis::Error>(())
+    }
+    .await;
+
+    if let Err(e) = result {
+        tracing::error!("Could not queue webhook event {}: {}", event, e);
+    }
+}
+
+/// Takes a due delivery for this worker, false when another one was faster
+async fn claim(rb: &Rbatis, delivery: &WebhookDelivery) -> Result<bool, rbatis::Error> {
+    let lease = Utc::now().naive_utc() + Duration::seconds(LEASE_SECS);
+    let result = rb
+        .exec(
+            "update webhook_delivery set attempts = attempts + 1, next_attempt_at = ? \
+             where id = ? and status = ? and attempts = ?",
+            vec![
+                Bson::from(lease.to_string()),
+                Bson::from(delivery.id),
+                Bson::from(PENDING),
+                Bson::from(delivery.attempts),
+            ],
+        )
+        .await?;
+
+    Ok(result.rows_affected == 1)
+}
+
+/// POSTs the payload, returns the status of the answer or why there was none
+async fn send(
+    client: &reqwest::Client,
+    hook: &Webhook,
+    delivery: &WebhookDelivery,
+) -> (Option<u32>, Option<String>) {
+    let timestamp = Utc::now().timestamp();
+    let signature = sign(&hook.secret, timestamp, &delivery.payload);
+    let response = client
+        .post(&hook.url)
+        .header("Content-Type", "application/json")
+        .header("User-Agent", "petclinic-webhooks")
+        .header("X-Petclinic-Event", &delivery.event)
+        .header("X-Petclinic-Delivery", delivery.id.to_string())
+        .header("X-Petclinic-Timestamp", timestamp.to_string())
+        .header("X-Petclinic-Signature", format!("sha256={}", signature))
+        .body(delivery.payload.clone())
+        .send()
+        .await;
+
+    match response {
+        Ok(r) if r.status().is_success() => (Some(r.status().as_u16() as u32), None),
+        Ok(r) => (
+            Some(r.status().as_u16() as u32),
+            Some(format!("receiver answered {}", r.status())),
+        ),
+        Err(e) => (None, Some(e.to_string())),
+    }
+}
+
+/// Records the outcome of an attempt: delivered, retried later or given up
+async fn record(
+    rb: &Rbatis,
+    delivery: &WebhookDelivery,
+    response_status: Option<u32>,
+    error: Option<String>,
+) -> Result<(), rbatis::Error> {
+    let now = Utc::now().naive_utc();
+    let attempts = delivery.attempts + 1;
+    let (status, next_attempt_at, delivered_at) = match &error {
+        None => (DELIVERED, now, Bson::from(now.to_string())),
+        Some(_) if attempts >= MAX_ATTEMPTS => (FAILED, now, Bson::Null),
+        Some(_) => (PENDING, now + retry_delay(attempts), Bson::Null),
+    };
+    let error = match error {
+        Some(e) => Bson::from(e.chars().take(MAX_ERROR_LENGTH).collect::<String>()),
+        None => Bson::Null,
+    };
+
+    rb.exec(
+        "update webhook_delivery set status = ?, next_attempt_at = ?, response_status = ?, \
+         last_error = ?, delivered_at = ? where id = ?",
+        vec![
+            Bson::from(status),
+            Bson::from(next_attempt_at.to_string()),
+            response_status.map_or(Bson::Null, Bson::from),
+            error,
+            delivered_at,
+            Bson::from(delivery.id),
+        ],
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Sends the deliveries that are due, returns how many were attempted
+pub async fn deliver_due(rb: &Rbatis, client: &reqwest::Client) -> Result<usize, rbatis::Error> {
+    let w = rb
+        .new_wrapper()
+        .eq("status", PENDING)
+        .le("next_attempt_at", Utc::now().naive_utc().to_string())
+        .order_by(true, &["next_attempt_at"])
+        .limit(BATCH_SIZE);
+    let due: Vec<WebhookDelivery> = rb.fetch_list_by_wrapper(w).await?;
+
+    let mut attempted = 0;
+    for delivery in due {
+        if !claim(rb, &delivery).await? {
+            continue;
+        }
+        let w = rb.new_wrapper().eq("id", delivery.webhook_id);
+        let hook: Option<Webhook> = rb.fetch_by_wrapper(w).await?;
+        let (response_status, error) = match hook {
+            Some(hook) if hook.active => send(client, &hook, &delivery).await,
+            Some(_) => (None, Some("webhook disabled".to_string())),
+            None => continue,
+        };
+        record(rb, &delivery, response_status, error).await?;
+        attempted += 1;
+    }
+
+    Ok(attempted)
+}
+
+/// Background task of the server sending the queued deliveries. Several instances can run
+/// it, each delivery is claimed before being sent.
+pub async fn worker(state: Arc<Context>) {
+    let client = reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(TIMEOUT_SECS))
+        .build()
+        .expect("webhook HTTP client");
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(POLL_SECS));
+
+    loop {
+        interval.tick().await;
+        if let Err(e) = deliver_due(&state.rb, &client).await {
+            tracing::error!("Webhook deliveries failed: {}", e);
+        }
+    }
+}
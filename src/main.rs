@@ -1,13 +1,15 @@
 use std::{
     error::Error,
+    net::SocketAddr,
     sync::{Arc, Mutex},
 };
 
 use axum::{
     async_trait,
-    extract::{Extension, FromRequest, RequestParts},
+    extract::{Extension, FromRequest, Query, RequestParts},
+    handler::Handler,
     http::StatusCode,
-    middleware::from_extractor,
+    middleware::{from_extractor, from_fn},
     response::{Html, IntoResponse, Redirect, Response},
     routing::{get, get_service, post},
     Router,
@@ -19,16 +21,25 @@ use handlers::*;
 use petclinic::Env;
 
 use argh::FromArgs;
-use logic::users::User;
+use logic::{
+    clinics::{self, ActiveClinic, Clinic, Scope},
+    events::{self, Change},
+    rate_limit,
+    saved_searches::{self, SavedSearch},
+    users::User,
+};
 
 use rbatis::rbatis::Rbatis;
 use redis::{Commands, Connection, RedisError};
 
-use serde_json::Value;
+use serde::Deserialize;
+use serde_json::{json, Value};
 use tera::Tera;
+use tokio::sync::broadcast;
 
 use tower_http::{services::ServeDir, trace::TraceLayer};
 use tracing::{debug, info};
+mod commands;
 mod handlers;
 mod logic;
 
@@ -42,12 +53,19 @@ struct Args {
     /// web service port to bind to
     #[argh(option, default = "3000")]
     port: u16,
+
+    #[argh(subcommand)]
+    command: Option<commands::Command>,
 }
 
 pub struct Context {
     pub rb: Rbatis,
     pub env: Env,
     pub redis_connection: Mutex<Connection>,
+    /// pet and vet changes of every instance, for the `/events` streams
+    pub changes: broadcast::Sender<Change>,
+    /// rate limit counters of this instance, for when Redis fails
+    pub rate_limits: rate_limit::MemoryCounters,
 }
 
 #[derive(Debug)]
@@ -57,7 +75,13 @@ pub struct AppError {
 
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        Html::from(format!("Oh, something bad happened: {}", &self.inner)).into_response()
+        let mut response =
+            Html::from(format!("Oh, something bad happened: {}", &self.inner)).into_response();
+        // answered as JSON instead to the clients asking for it, see `negotiate::errors`
+        response
+            .extensions_mut()
+            .insert(negotiate::ErrorMessage(self.inner.to_string()));
+        response
     }
 }
 
@@ -86,31 +110,50 @@ async fn main() {
 
     info!("Env: {env_name}");
     let env = petclinic::from_str(env_name);
-    let state = create_context(env.clone()).await;
+
+    if let Some(command) = args.command {
+        if let Err(e) = commands::run(&env, command).await {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let state = Arc::new(create_context(env.clone()).await);
+    tokio::spawn(logic::webhooks::worker(state.clone()));
 
     let app = get_public_routes()
         .merge(get_protected_routes())
+        .nest("/api/v1", get_api_routes())
+        .merge(get_graphql_routes())
         .fallback(get(|| async { "fallback route?" }))
+        .layer(from_fn(negotiate::errors))
         .layer(TraceLayer::new_for_http())
-        .route_layer(Extension(Arc::new(state)))
+        .route_layer(Extension(state))
         .route_layer(Extension(Arc::new(env)))
         .route_layer(Extension(get_tera_instance()));
 
     axum::Server::bind(&format!("0.0.0.0:{}", args.port).parse().unwrap())
-        .serve(app.into_make_service())
+        // the peer addresses are the keys of the rate limits
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
         .await
         .unwrap();
 
     info!("Server started");
 }
 
-async fn create_context(env: Env) -> Context {
+async fn connect_database(env: &Env) -> Rbatis {
     let rb = Rbatis::new();
     let dsn = format!(
         "mysql://{}:{}@{}/{}",
         env.db_username, env.db_password, env.db_server, env.db_name
     );
     rb.link(dsn.as_str()).await.unwrap();
+    rb
+}
+
+async fn create_context(env: Env) -> Context {
+    let rb = connect_database(&env).await;
 
     let redis_url = match &env.redis_password {
         Some(password) => format!("redis://:{}@{}", password, env.redis_server),
@@ -119,18 +162,26 @@ async fn create_context(env: Env) -> Context {
 
     let client = redis::Client::open(redis_url).unwrap();
     let redis_connection = client.get_connection().unwrap();
+    let (changes, _) = broadcast::channel(events::BUFFER_SIZE);
+    events::subscribe(client, changes.clone());
 
     Context {
         rb,
         env,
         redis_connection: Mutex::new(redis_connection),
+        changes,
+        rate_limits: Default::default(),
     }
 }
 fn get_public_routes() -> Router {
     Router::new()
         .route("/", get(home::home))
         .route("/logout", get(auth::logout))
-        .route("/login", get(auth::login).post(auth::post_login))
+        .route(
+            "/login",
+            get(auth::login).post(auth::post_login.layer(from_fn(handlers::rate_limit::login))),
+        )
+        .route("/api/openapi.json", get(handlers::api::openapi::spec))
         .nest(
             "/static",
             get_service(ServeDir::new("static")).handle_error(|_| async move {}),
@@ -138,17 +189,87 @@ fn get_public_routes() -> Router {
 }
 fn get_protected_routes() -> Router {
     Router::new()
+        .route("/dashboard", get(dashboard::dashboard))
+        .route("/reports/workload", get(reports::workload))
         .route("/vets", get(vets::list))
         .route("/vets/save", post(vets::save))
+        .route("/vets/export", get(exports::vets))
         .route("/vets/:id", get(vets::get))
         .route("/pets", get(pets::list))
         .route("/pets/save", post(pets::save))
+        .route("/pets/bulk", post(pets::bulk))
+        .route("/pets/export", get(exports::pets))
+        .route("/visits/export", get(exports::visits))
         .route("/pets/:id", get(pets::get))
         .route("/vets/delete/:id", get(vets::delete))
+        .route(
+            "/vets/reassign/:id",
+            get(vets::reassign).post(vets::post_reassign),
+        )
         .route("/pets/delete/:id", get(pets::delete))
+        .route("/pets/merge/:id", get(pets::merge).post(pets::post_merge))
+        .route(
+            "/pets/transfer/:id",
+            get(pets::transfer).post(pets::post_transfer),
+        )
+        .route("/clinics/switch", post(handlers::clinics::switch))
+        .route("/import", get(imports::upload).post(imports::post_upload))
+        .route("/import/run", post(imports::run))
+        .route("/backup", get(backups::page))
+        .route("/backup/download", get(backups::download))
+        .route("/search", get(search::search))
+        .route("/searches", get(searches::list))
+        .route("/searches/save", post(searches::save))
+        .route("/searches/default/:id", get(searches::toggle_default))
+        .route("/searches/delete/:id", get(searches::delete))
+        .route("/api/explorer", get(handlers::api::openapi::explorer))
+        .route("/events", get(handlers::events::stream))
+        .route("/tokens", get(tokens::list).post(tokens::create))
+        .route("/tokens/revoke/:id", get(tokens::revoke))
+        .route("/webhooks", get(webhooks::list).post(webhooks::create))
+        .route("/webhooks/:id", get(webhooks::get))
+        .route("/webhooks/toggle/:id", get(webhooks::toggle))
+        .route("/webhooks/delete/:id", get(webhooks::delete))
+        .route("/webhooks/ping/:id", get(webhooks::ping))
+        .route(
+            "/webhooks/:id/redeliver/:delivery",
+            get(webhooks::redeliver),
+        )
         .route_layer(from_extractor::<User>())
 }
 
+fn get_api_routes() -> Router {
+    use handlers::api;
+
+    Router::new()
+        .route("/pets", get(api::pets::list).post(api::pets::create))
+        .route(
+            "/pets/:id",
+            get(api::pets::get)
+                .put(api::pets::update)
+                .patch(api::pets::patch)
+                .delete(api::pets::delete),
+        )
+        .route("/vets", get(api::vets::list).post(api::vets::create))
+        .route(
+            "/vets/:id",
+            get(api::vets::get)
+                .put(api::vets::update)
+                .patch(api::vets::patch)
+                .delete(api::vets::delete),
+        )
+        .route("/visits", get(api::visits::list).post(api::visits::create))
+        .route(
+            "/visits/:id",
+            get(api::visits::get)
+                .put(api::visits::update)
+                .patch(api::visits::patch)
+                .delete(api::visits::delete),
+        )
+        .route_layer(from_fn(handlers::rate_limit::api))
+        .route_layer(from_extractor::<api::ApiUser>())
+}
+
 struct Principal {
     user: Option<User>,
 }
@@ -167,6 +288,64 @@ impl tera::Function for Principal {
     }
 }
 
+struct IsAdmin(bool);
+
+impl tera::Function for IsAdmin {
+    fn call(
+        &self,
+        _args: &std::collections::HashMap<String, serde_json::Value>,
+    ) -> tera::Result<Value> {
+        tera::Result::Ok(Value::Bool(self.0))
+    }
+}
+
+struct ClinicSwitcher {
+    active: u32,
+    clinics: Vec<Clinic>,
+}
+
+impl tera::Function for ClinicSwitcher {
+    fn call(
+        &self,
+        _args: &std::collections::HashMap<String, serde_json::Value>,
+    ) -> tera::Result<Value> {
+        let clinics = self
+            .clinics
+            .iter()
+            .map(|c| json!({ "id": c.id, "name": c.name, "active": c.id == self.active }))
+            .collect();
+
+        tera::Result::Ok(Value::Array(clinics))
+    }
+}
+
+struct SavedSearches {
+    searches: Vec<SavedSearch>,
+}
+
+impl tera::Function for SavedSearches {
+    fn call(
+        &self,
+        _args: &std::collections::HashMap<String, serde_json::Value>,
+    ) -> tera::Result<Value> {
+        let searches = self
+            .searches
+            .iter()
+            .map(|s| json!({ "id": s.id, "name": s.name, "query": s.query, "is_default": s.is_default }))
+            .collect();
+
+        tera::Result::Ok(Value::Array(searches))
+    }
+}
+
+fn get_graphql_routes() -> Router {
+    Router::new()
+        .route("/graphql", get(graphql::get).post(graphql::post))
+        .route_layer(from_fn(handlers::rate_limit::api))
+        .route_layer(from_extractor::<handlers::api::ApiUser>())
+        .layer(Extension(graphql::schema()))
+}
+
 fn get_tera_instance() -> Tera {
     debug!("Creating Tera instance");
     let mut tera = match Tera::new("templates/**/*") {
@@ -188,6 +367,11 @@ where
     type Rejection = (StatusCode, Redirect);
 
     async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        // already checked by the route layer
+        if let Some(user) = req.extensions().get::<User>() {
+            return Ok(user.clone());
+        }
+
         let Extension(context) = Extension::<Arc<Context>>::from_request(req).await.unwrap();
         let Extension(env) = Extension::<Arc<Env>>::from_request(req).await.unwrap();
         let cookiejar = Option::<CookieJar>::from_request(req)
@@ -195,33 +379,75 @@ where
             .unwrap()
             .unwrap();
 
-        let tera = req.extensions_mut().get_mut::<Tera>().unwrap();
-
         if cookiejar.get("axum_session").is_none() {
             debug!("Session cookie not found, redirecting to login url");
             return Err((StatusCode::TEMPORARY_REDIRECT, Redirect::to("/login")));
         }
 
-        // check if the session cookie is valid against  redis
-        let mut connection = context.redis_connection.lock().unwrap();
         let cookie = cookiejar.get("axum_session").unwrap();
+        let clinic_key = clinics::redis_key(cookie.value());
 
-        let valid_session: Result<User, RedisError> = connection.get(cookie.value());
+        // check if the session cookie is valid against  redis
+        let (valid_session, stored_clinic) = {
+            let mut connection = context.redis_connection.lock().unwrap();
+            let valid_session: Result<User, RedisError> = connection.get(cookie.value());
+            let stored_clinic: Option<u32> = connection.get(&clinic_key).unwrap_or(None);
+            if valid_session.is_ok() {
+                // refresh the keys ttl
+                let _redis_response: Result<(), RedisError> =
+                    connection.expire(cookie.value(), context.env.session_timeout);
+                let _redis_response: Result<(), RedisError> =
+                    connection.expire(&clinic_key, context.env.session_timeout);
+            }
+            (valid_session, stored_clinic)
+        };
 
         match valid_session {
             Ok(user) => {
-                // refresh the key ttl
-                let _redis_response: Result<(), RedisError> =
-                    connection.expire(cookie.value(), context.env.session_timeout);
+                let available = clinics::of_user(&context.rb, &user)
+                    .await
+                    .unwrap_or_else(|e| {
+                        tracing::error!("Cannot read the clinics of {}: {}", user.username, e);
+                        Vec::new()
+                    });
+                let searches = saved_searches::of_user(&context.rb, &user)
+                    .await
+                    .unwrap_or_else(|e| {
+                        tracing::error!(
+                            "Cannot read the saved searches of {}: {}",
+                            user.username,
+                            e
+                        );
+                        Vec::new()
+                    });
+                let active = stored_clinic
+                    .filter(|id| available.iter().any(|c| c.id == *id))
+                    .unwrap_or(user.clinic_id);
+                req.extensions_mut().insert(ActiveClinic {
+                    id: active,
+                    is_admin: user.is_admin,
+                });
+
+                let tera = req.extensions_mut().get_mut::<Tera>().unwrap();
                 tera.register_function(
                     "principal",
                     Principal {
                         user: Some(user.clone()),
                     },
                 );
+                tera.register_function("is_admin", IsAdmin(user.is_admin));
+                tera.register_function(
+                    "clinics",
+                    ClinicSwitcher {
+                        active,
+                        clinics: available,
+                    },
+                );
+                tera.register_function("saved_searches", SavedSearches { searches });
                 if env.name == "dev" {
                     tera.full_reload().unwrap();
                 }
+                req.extensions_mut().insert(user.clone());
 
                 return Ok(user);
             }
@@ -232,3 +458,35 @@ where
         }
     }
 }
+
+#[derive(Deserialize, Default)]
+struct ScopeParams {
+    #[serde(default)]
+    all_clinics: bool,
+}
+
+#[async_trait]
+impl<B> FromRequest<B> for Scope
+where
+    B: Send,
+{
+    type Rejection = (StatusCode, Redirect);
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        // Set by the User extractor guarding the protected routes
+        let active = match req.extensions().get::<ActiveClinic>() {
+            Some(active) => *active,
+            None => return Err((StatusCode::TEMPORARY_REDIRECT, Redirect::to("/login"))),
+        };
+        let params = Query::<ScopeParams>::from_request(req)
+            .await
+            .map(|Query(params)| params)
+            .unwrap_or_default();
+
+        if params.all_clinics && active.is_admin {
+            Ok(Scope::All)
+        } else {
+            Ok(Scope::Clinic(active.id))
+        }
+    }
+}
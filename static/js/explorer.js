@@ -0,0 +1,188 @@
+// Renders the operations of /api/openapi.json with a form to try each of them
+(function () {
+  "use strict";
+
+  var root = document.getElementById("explorer");
+  var METHOD_CLASS = {
+    get: "is-info",
+    post: "is-success",
+    put: "is-warning",
+    patch: "is-warning",
+    delete: "is-danger"
+  };
+
+  function el(tag, attrs, children) {
+    var node = document.createElement(tag);
+    Object.keys(attrs || {}).forEach(function (key) {
+      if (key === "text") {
+        node.textContent = attrs[key];
+      } else {
+        node.setAttribute(key, attrs[key]);
+      }
+    });
+    (children || []).forEach(function (child) {
+      node.appendChild(child);
+    });
+    return node;
+  }
+
+  function resolve(spec, schema) {
+    while (schema && schema.$ref) {
+      var name = schema.$ref.split("/").pop();
+      schema = spec.components.schemas[name];
+    }
+    return schema || {};
+  }
+
+  // A sample value of a schema, used to prefill request bodies
+  function sample(spec, schema, depth) {
+    schema = resolve(spec, schema);
+    if (depth > 4) {
+      return null;
+    }
+    if (schema.example !== undefined) {
+      return schema.example;
+    }
+    if (schema.allOf) {
+      return sample(spec, schema.allOf[0], depth + 1);
+    }
+    var type = Array.isArray(schema.type) ? schema.type[0] : schema.type;
+    switch (type) {
+      case "object":
+        var value = {};
+        Object.keys(schema.properties || {}).forEach(function (key) {
+          value[key] = sample(spec, schema.properties[key], depth + 1);
+        });
+        return value;
+      case "array":
+        return [];
+      case "integer":
+      case "number":
+        return 0;
+      case "boolean":
+        return false;
+      case "string":
+        return schema.format === "date-time" ? new Date().toISOString().slice(0, 19) : "";
+      default:
+        return null;
+    }
+  }
+
+  function operation(spec, path, method, op) {
+    var inputs = {};
+    var fields = (op.parameters || []).map(function (param) {
+      var input = el("input", {
+        class: "input is-small",
+        type: "text",
+        placeholder: param.description || ""
+      });
+      inputs[param.name] = { param: param, input: input };
+      return el("div", { class: "field" }, [
+        el("label", { class: "label is-small", text: param.name + (param.required ? " *" : "") }),
+        el("div", { class: "control" }, [input])
+      ]);
+    });
+
+    var body = null;
+    if (op.requestBody) {
+      var schema = op.requestBody.content["application/json"].schema;
+      body = el("textarea", { class: "textarea is-small is-family-monospace", rows: "8" });
+      body.value = JSON.stringify(sample(spec, schema, 0), null, 2);
+      fields.push(el("div", { class: "field" }, [
+        el("label", { class: "label is-small", text: "Body" }),
+        el("div", { class: "control" }, [body])
+      ]));
+    }
+
+    var status = el("p", { class: "has-text-weight-semibold mt-3" });
+    var output = el("pre", { class: "is-hidden" });
+    var send = el("button", { class: "button is-primary is-small", type: "button", text: "Send" });
+
+    send.addEventListener("click", function () {
+      var url = path;
+      var query = new URLSearchParams();
+      Object.keys(inputs).forEach(function (name) {
+        var value = inputs[name].input.value;
+        if (inputs[name].param.in === "path") {
+          url = url.replace("{" + name + "}", encodeURIComponent(value));
+        } else if (value !== "") {
+          query.append(name, value);
+        }
+      });
+      if (query.toString()) {
+        url += "?" + query.toString();
+      }
+
+      var request = { method: method.toUpperCase(), credentials: "same-origin", headers: {} };
+      if (body) {
+        request.headers["Content-Type"] = "application/json";
+        request.body = body.value;
+      }
+      status.textContent = "Sending...";
+      fetch(url, request).then(function (response) {
+        status.textContent = request.method + " " + url + " → " + response.status + " " + response.statusText;
+        return response.text();
+      }).then(function (text) {
+        try {
+          text = JSON.stringify(JSON.parse(text), null, 2);
+        } catch (e) {
+          // not JSON, shown as is
+        }
+        output.textContent = text;
+        output.classList.toggle("is-hidden", text === "");
+      }).catch(function (e) {
+        status.textContent = "Request failed: " + e;
+      });
+    });
+
+    var responses = Object.keys(op.responses || {}).map(function (code) {
+      return el("li", { text: code + " " + op.responses[code].description });
+    });
+
+    var content = el("div", { class: "card-content is-hidden" }, [
+      el("p", { class: "mb-3", text: op.description || "" }),
+      el("div", { class: "columns" }, [
+        el("div", { class: "column" }, fields.concat([send, status, output])),
+        el("div", { class: "column is-4" }, [
+          el("p", { class: "has-text-weight-semibold", text: "Responses" }),
+          el("ul", {}, responses)
+        ])
+      ])
+    ]);
+
+    var header = el("header", { class: "card-header is-clickable" }, [
+      el("p", { class: "card-header-title" }, [
+        el("span", { class: "tag mr-3 " + (METHOD_CLASS[method] || ""), text: method.toUpperCase() }),
+        el("code", { text: path }),
+        el("span", { class: "has-text-grey ml-3 has-text-weight-normal", text: op.summary || "" })
+      ])
+    ]);
+    header.addEventListener("click", function () {
+      content.classList.toggle("is-hidden");
+    });
+
+    return el("div", { class: "card mb-3" }, [header, content]);
+  }
+
+  fetch("/api/openapi.json").then(function (response) {
+    return response.json();
+  }).then(function (spec) {
+    root.textContent = "";
+    var tags = {};
+    Object.keys(spec.paths).forEach(function (path) {
+      Object.keys(spec.paths[path]).forEach(function (method) {
+        var op = spec.paths[path][method];
+        var tag = (op.tags || ["other"])[0];
+        (tags[tag] = tags[tag] || []).push(operation(spec, path, method, op));
+      });
+    });
+    Object.keys(tags).forEach(function (tag) {
+      root.appendChild(el("h2", { class: "subtitle mt-5", text: tag }));
+      tags[tag].forEach(function (card) {
+        root.appendChild(card);
+      });
+    });
+  }).catch(function (e) {
+    root.textContent = "Could not load the specification: " + e;
+  });
+})();